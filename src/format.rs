@@ -0,0 +1,35 @@
+//! Formatting which is shared between the interface & the commands that just print something.
+
+use std::time::Duration;
+
+/// Formats a longer duration, like `1h 5m`, which is used for how long a session lasted.
+pub fn format_long_duration(duration: &Duration) -> String {
+    let seconds = duration.as_secs();
+    let (hours, minutes) = (seconds / 3600, seconds / 60 % 60);
+
+    if hours > 0 {
+        format!("{hours}h {minutes}m")
+    } else if minutes > 0 {
+        format!("{minutes}m {}s", seconds % 60)
+    } else {
+        format!("{seconds}s")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::format_long_duration;
+
+    #[test]
+    fn long_durations_leave_out_the_smallest_unit() {
+        let format = |x| format_long_duration(&Duration::from_secs(x));
+
+        assert_eq!(format(0), "0s");
+        assert_eq!(format(59), "59s");
+        assert_eq!(format(61), "1m 1s");
+        assert_eq!(format(3600), "1h 0m");
+        assert_eq!(format(3600 * 25 + 60 * 5 + 9), "25h 5m");
+    }
+}
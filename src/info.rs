@@ -0,0 +1,62 @@
+//! Responsible for `--info`, which describes the build & setup of lowfi for bug reports.
+
+use crate::paths;
+use crate::player::saved;
+use crate::tracks::list;
+use crate::Args;
+
+/// All of the optional features, alongside whether they were enabled when lowfi was built.
+const FEATURES: [(&str, bool); 6] = [
+    ("mpris", cfg!(feature = "mpris")),
+    ("notify", cfg!(feature = "notify")),
+    ("scrobble", cfg!(feature = "scrobble")),
+    ("listenbrainz", cfg!(feature = "listenbrainz")),
+    ("discord", cfg!(feature = "discord")),
+    ("visualizer", cfg!(feature = "visualizer")),
+];
+
+/// Prints the version, the enabled features, where tracks would be played from & the relevant directories.
+///
+/// The track source is worked out the same way as in [Player::new](crate::player::Player::new),
+/// but nothing is actually fetched.
+pub async fn print(args: &Args) -> eyre::Result<()> {
+    let features: Vec<&str> = FEATURES
+        .iter()
+        .filter(|(_, enabled)| *enabled)
+        .map(|(name, _)| *name)
+        .collect();
+
+    let source = if args.bookmarks {
+        saved::path(saved::BOOKMARKS).await?.display().to_string()
+    } else if let Some(dir) = &args.dir {
+        dir.display().to_string()
+    } else if let Some(playlist) = &args.playlist {
+        playlist.display().to_string()
+    } else if args.tracks.is_empty() {
+        format!("{} (built-in)", list::BASE_URL)
+    } else {
+        args.tracks
+            .iter()
+            .map(|x| x.as_str())
+            .collect::<Vec<&str>>()
+            .join(", ")
+    };
+
+    let cache = if args.no_cache {
+        String::from("disabled")
+    } else {
+        paths::cache()?.display().to_string()
+    };
+
+    println!("lowfi {}", env!("CARGO_PKG_VERSION"));
+    if features.is_empty() {
+        println!("features: none");
+    } else {
+        println!("features: {}", features.join(", "));
+    }
+    println!("tracks: {source}");
+    println!("cache: {cache}");
+    println!("config: {}", paths::config()?.display());
+
+    Ok(())
+}
@@ -1,5 +1,11 @@
+use std::path::PathBuf;
+
 use clap::{Parser, Subcommand};
+use reqwest::Url;
 
+mod format;
+mod info;
+mod paths;
 mod play;
 mod player;
 mod scrape;
@@ -21,6 +27,217 @@ struct Args {
     #[clap(long, short)]
     paused: bool,
 
+    /// The most times per second that the interface will be redrawn.
+    #[clap(long, value_name = "N", default_value_t = 12, value_parser = clap::value_parser!(u8).range(1..=60))]
+    fps: u8,
+
+    /// The widest that the interface can be, in columns, instead of the usual 73.
+    /// This has to be at least 20, since that's needed for the controls to fit.
+    #[clap(long, value_name = "N", value_parser = clap::value_parser!(u16).range(20..))]
+    width: Option<u16>,
+
+    /// Whether to let the interface fill the whole width of the terminal.
+    #[clap(long, conflicts_with = "width")]
+    full_width: bool,
+
+    /// How to draw the box around the interface, for terminals or fonts that lack box-drawing characters.
+    #[clap(long, value_name = "STYLE", value_enum, default_value_t = player::ui::theme::BorderStyle::Unicode)]
+    border: player::ui::theme::BorderStyle,
+
+    /// Whether to show the names of tracks exactly as they are in the list, without cleaning them up.
+    #[clap(long)]
+    raw_names: bool,
+
+    /// Whether to leave out how long lowfi played for when quitting.
+    #[clap(long, short)]
+    quiet: bool,
+
+    /// Whether quitting has to be confirmed by pressing `y`, which ctrl+c skips.
+    #[clap(long)]
+    confirm_quit: bool,
+
+    /// Whether to play without any interface at all, leaving the terminal alone,
+    /// which means lowfi can only be controlled with MPRIS or `--ipc`.
+    #[clap(long)]
+    headless: bool,
+
+    /// Whether to stick to plain key events, for terminals where the enhanced keyboard protocol garbles input.
+    #[clap(long)]
+    no_keyboard_enhancement: bool,
+
+    /// Whether to use vim-style hjkl keys to control the volume.
+    #[clap(long)]
+    vim_keys: bool,
+
+    /// Quits lowfi after this many minutes, fading out the volume beforehand.
+    #[clap(long, value_name = "MINUTES", value_parser = clap::value_parser!(u64).range(1..))]
+    sleep: Option<u64>,
+
+    /// A directory of audio files to play from, instead of downloading tracks.
+    #[clap(long, value_name = "PATH")]
+    dir: Option<PathBuf>,
+
+    /// A URL to a custom track list, where each line is a track relative to the URL.
+    /// This can be given more than once, in which case the rest are mirrors of the first.
+    #[clap(long, value_name = "URL", conflicts_with = "dir")]
+    tracks: Vec<Url>,
+
+    /// A `.m3u`, `.m3u8` or `.pls` playlist to play from, which can have both files & URLs.
+    #[clap(long, value_name = "FILE", conflicts_with_all = ["dir", "tracks"])]
+    playlist: Option<PathBuf>,
+
+    /// Whether to only play tracks which have been bookmarked.
+    #[clap(long, conflicts_with_all = ["dir", "tracks", "playlist"])]
+    bookmarks: bool,
+
+    /// Only plays tracks with names that contain this, ignoring case.
+    #[clap(long, value_name = "SUBSTRING")]
+    filter: Option<String>,
+
+    /// The track to start with, which is either its position in the list, starting from 1, or part of its name.
+    #[clap(long, value_name = "NAME_OR_INDEX")]
+    start: Option<String>,
+
+    /// Whether to quit once a single track has been played, which is also the `--start` track if given.
+    #[clap(long)]
+    once: bool,
+
+    /// Whether to play the track list in order, instead of shuffling it.
+    #[clap(long)]
+    sequential: bool,
+
+    /// How many seconds to count down for before the first track starts, which is handy for recordings.
+    #[clap(long, value_name = "SECONDS", default_value_t = 0)]
+    countdown: u64,
+
+    /// What to do after the last track, when playing the list in order.
+    #[clap(long, value_name = "ACTION", value_enum, default_value_t = tracks::list::AtEnd::Loop)]
+    at_end: tracks::list::AtEnd,
+
+    /// How long to fade in for on startup, and to fade out for when quitting, in milliseconds.
+    #[clap(long, value_name = "MS", default_value_t = 500)]
+    fade: u64,
+
+    /// How long to crossfade between tracks, in seconds.
+    ///
+    /// Without a crossfade, tracks are played back to back with no gap in between.
+    #[clap(long, value_name = "SECONDS", default_value_t = 0)]
+    crossfade: u64,
+
+    /// How many seconds of silence to leave between tracks, which isn't used while crossfading.
+    #[clap(long, value_name = "SECONDS", default_value_t = 0)]
+    gap: u64,
+
+    /// Whether to adjust the volume of each track, so that they're all similarly loud.
+    #[clap(long)]
+    normalize: bool,
+
+    /// The highest that the volume can be turned up to, as a percentage.
+    /// Anything above 100 amplifies the audio, which can make louder tracks clip.
+    #[clap(long, value_name = "PERCENT", default_value_t = 100, value_parser = clap::value_parser!(u16).range(1..=500))]
+    max_volume: u16,
+
+    /// The volume to start at, as a percentage, instead of the one from last time.
+    /// This isn't saved for the next session unless the volume is changed afterwards.
+    #[clap(long, value_name = "PERCENT")]
+    volume: Option<u16>,
+
+    /// How the volume is turned into the actual loudness, where `log` makes
+    /// the lower volumes more useful since it's closer to how loudness is heard.
+    #[clap(long, value_name = "CURVE", value_enum, default_value_t = player::VolumeCurve::Linear)]
+    volume_curve: player::VolumeCurve,
+
+    /// How many tracks to download ahead of time, which helps with a spotty connection
+    /// but takes up more memory. This is also the most that can be downloading at once.
+    #[clap(long, value_name = "N", default_value_t = 1, value_parser = clap::value_parser!(u8).range(1..))]
+    buffer: u8,
+
+    /// How many of the most recently played tracks to avoid when shuffling.
+    #[clap(long, value_name = "N", default_value_t = 5)]
+    no_repeat_window: usize,
+
+    /// Whether to disable saving downloaded tracks to the cache.
+    #[clap(long)]
+    no_cache: bool,
+
+    /// The maximum size of the track cache, in megabytes.
+    #[clap(long, value_name = "MB", default_value_t = 512)]
+    cache_size: u64,
+
+    /// Where to keep downloaded tracks, instead of the usual cache directory.
+    #[clap(long, value_name = "PATH")]
+    cache_dir: Option<PathBuf>,
+
+    /// Where to look for keybinds, themes & saved files, instead of the usual config directory.
+    #[clap(long, value_name = "PATH")]
+    config_dir: Option<PathBuf>,
+
+    /// Whether to send a desktop notification when the track changes.
+    #[cfg(feature = "notify")]
+    #[clap(long)]
+    notify: bool,
+
+    /// Whether to show a small visualizer of the audio next to the progress bar.
+    #[cfg(feature = "visualizer")]
+    #[clap(long)]
+    visualizer: bool,
+
+    /// How much of a track has to be played before it's scrobbled, as a percentage.
+    #[cfg(any(feature = "scrobble", feature = "listenbrainz"))]
+    #[clap(long, value_name = "PERCENT", default_value_t = 50, value_parser = clap::value_parser!(u8).range(1..=100))]
+    scrobble_threshold: u8,
+
+    /// Tracks shorter than this many seconds are never scrobbled.
+    #[cfg(any(feature = "scrobble", feature = "listenbrainz"))]
+    #[clap(long, value_name = "SECONDS", default_value_t = 30)]
+    scrobble_min_seconds: u64,
+
+    /// The audio output device to use, which can be any part of its name.
+    #[clap(long, value_name = "NAME")]
+    device: Option<String>,
+
+    /// Lists all of the audio output devices, and then exits.
+    #[clap(long)]
+    list_devices: bool,
+
+    /// Whether to pause when an output device is unplugged, or the default device changes.
+    #[clap(long)]
+    pause_on_unplug: bool,
+
+    /// Whether to pause while the terminal isn't focused, which not every terminal reports.
+    #[clap(long, conflicts_with = "headless")]
+    pause_on_unfocus: bool,
+
+    /// Prints the version, the enabled features & where tracks come from, and then exits.
+    /// This is handy to include in bug reports.
+    #[clap(long)]
+    info: bool,
+
+    /// Prints the most recently played tracks, and then exits.
+    #[clap(long)]
+    history: bool,
+
+    /// The maximum size of the play history, in kilobytes.
+    #[clap(long, value_name = "KB", default_value_t = 1024)]
+    history_size: u64,
+
+    /// A unix socket to create, which sends the status of lowfi as JSON & accepts commands.
+    #[cfg(unix)]
+    #[clap(long, value_name = "PATH")]
+    ipc: Option<PathBuf>,
+
+    /// Whether to print the last track & how many tracks were played as JSON when quitting.
+    #[clap(long)]
+    print_on_exit: bool,
+
+    /// A command to run whenever a new track starts, where `%t` is the name of the track.
+    #[clap(long, value_name = "COMMAND")]
+    on_track: Option<String>,
+
+    /// A file to append errors to, like when a track couldn't be decoded.
+    #[clap(long, value_name = "FILE")]
+    log: Option<PathBuf>,
+
     /// Whether to include ALSA & other logs.
     #[clap(long, short)]
     debug: bool,
@@ -44,11 +261,19 @@ enum Commands {
         #[clap(long, short)]
         include_full: bool,
     },
+
+    /// Prints how long you've listened for & your most played tracks, from the history.
+    Stats {
+        /// How many of the most played tracks to show.
+        #[clap(long, short, default_value_t = 10)]
+        top: usize,
+    },
 }
 
 #[tokio::main]
 async fn main() -> eyre::Result<()> {
     let cli = Args::parse();
+    paths::set(cli.cache_dir.clone(), cli.config_dir.clone());
 
     if let Some(command) = cli.command {
         match command {
@@ -56,7 +281,24 @@ async fn main() -> eyre::Result<()> {
                 extension,
                 include_full,
             } => scrape::scrape(extension, include_full).await,
+            Commands::Stats { top } => player::history::stats(top).await,
         }
+    } else if cli.list_devices {
+        let names = if cfg!(target_os = "linux") && !cli.debug {
+            player::Player::silently(player::devices::names)?
+        } else {
+            player::devices::names()?
+        };
+
+        for name in names {
+            println!("{name}");
+        }
+
+        Ok(())
+    } else if cli.info {
+        info::print(&cli).await
+    } else if cli.history {
+        player::history::print().await
     } else {
         play::play(cli).await
     }
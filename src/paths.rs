@@ -0,0 +1,57 @@
+//! Works out the directories where lowfi keeps its files, which follow
+//! the platform's conventions, like `$XDG_CONFIG_HOME` on Linux.
+//!
+//! The cache & config directories can also be changed with `--cache-dir` & `--config-dir`.
+
+use std::{path::PathBuf, sync::OnceLock};
+
+use eyre::eyre;
+
+/// The cache directory from `--cache-dir`, which is used instead of the usual one.
+static CACHE: OnceLock<PathBuf> = OnceLock::new();
+
+/// The config directory from `--config-dir`, which is used instead of the usual one.
+static CONFIG: OnceLock<PathBuf> = OnceLock::new();
+
+/// Sets the directories from `--cache-dir` & `--config-dir`, which should only happen once at startup.
+pub fn set(cache: Option<PathBuf>, config: Option<PathBuf>) {
+    if let Some(cache) = cache {
+        let _ = CACHE.set(cache);
+    }
+
+    if let Some(config) = config {
+        let _ = CONFIG.set(config);
+    }
+}
+
+/// Gets where downloaded tracks are kept, which is usually `~/.cache/lowfi` on Linux.
+pub fn cache() -> eyre::Result<PathBuf> {
+    if let Some(cache) = CACHE.get() {
+        return Ok(cache.clone());
+    }
+
+    Ok(dirs::cache_dir()
+        .ok_or(eyre!("Couldn't find cache directory"))?
+        .join("lowfi"))
+}
+
+/// Gets where the config & saved files are kept, which is usually `~/.config/lowfi` on Linux.
+pub fn config() -> eyre::Result<PathBuf> {
+    if let Some(config) = CONFIG.get() {
+        return Ok(config.clone());
+    }
+
+    Ok(dirs::config_dir()
+        .ok_or(eyre!("Couldn't find config directory"))?
+        .join("lowfi"))
+}
+
+/// Gets where the history is kept, which is usually `~/.local/state/lowfi` on Linux.
+///
+/// Platforms without a state directory will use the local data directory instead.
+pub fn state() -> eyre::Result<PathBuf> {
+    Ok(dirs::state_dir()
+        .or_else(dirs::data_local_dir)
+        .ok_or(eyre!("Couldn't find state directory"))?
+        .join("lowfi"))
+}
@@ -1,16 +1,23 @@
 //! Responsible for the basic initialization & shutdown of the audio server & frontend.
 
 use std::path::PathBuf;
+use std::sync::atomic::Ordering;
 use std::sync::Arc;
 
-use eyre::eyre;
+use eyre::ensure;
 use tokio::fs;
 use tokio::{sync::mpsc, task};
 
+use crate::paths;
+use crate::player::ui::keybinds::Keybinds;
+use crate::player::ui::theme::{self, Theme};
 use crate::player::Player;
 use crate::player::{ui, Messages};
 use crate::Args;
 
+/// The volume which is used if there isn't a valid `volume.txt`, as a percentage.
+const DEFAULT_VOLUME: u16 = 100;
+
 /// The attributes that are applied at startup.
 /// This includes the volume, but also the config file.
 ///
@@ -19,14 +26,21 @@ use crate::Args;
 pub struct InitialProperties {
     /// The volume, as a percentage.
     pub volume: u16,
+
+    /// The keybindings, loaded from `keybinds.toml` if it exists.
+    pub keybinds: Keybinds,
+
+    /// The colors of the interface, loaded from `theme.toml` if it exists.
+    pub theme: Theme,
+
+    /// Whether the controls start off hidden, either from `--minimalist` or from `minimalist.txt`.
+    pub minimalist: bool,
 }
 
 impl InitialProperties {
     /// Retrieves the config directory.
-    async fn config() -> eyre::Result<PathBuf> {
-        let config = dirs::config_dir()
-            .ok_or(eyre!("Couldn't find config directory"))?
-            .join(PathBuf::from("lowfi"));
+    pub async fn config() -> eyre::Result<PathBuf> {
+        let config = paths::config()?;
 
         if !config.exists() {
             fs::create_dir_all(&config).await?;
@@ -36,24 +50,52 @@ impl InitialProperties {
     }
 
     /// Loads the [InitialProperties], including the config and volume file.
-    pub async fn load() -> eyre::Result<Self> {
+    pub async fn load(args: &Args) -> eyre::Result<Self> {
         let config = Self::config().await?;
 
         let volume = config.join(PathBuf::from("volume.txt"));
 
-        // Basically just read from the volume file if it exists, otherwise return 100.
-        let volume = if volume.exists() {
+        // `--volume` always wins, but otherwise just read from the volume file if it exists, or return 100.
+        // If the file is malformed, then it'll also just fall back to 100,
+        // and it'll be overwritten with a valid volume when lowfi quits.
+        // Anything above `--max-volume` is lowered by the player when it's applied.
+        let volume = if let Some(volume) = args.volume {
+            ensure!(
+                volume <= args.max_volume,
+                "--volume can't be higher than --max-volume, which is {}%",
+                args.max_volume
+            );
+
+            volume
+        } else if volume.exists() {
             let contents = fs::read_to_string(volume).await?;
-            let stripped = contents.trim().strip_suffix("%").unwrap_or(&contents);
-            stripped
-                .parse()
-                .map_err(|_| eyre!("volume.txt file is invalid"))?
+            let trimmed = contents.trim();
+            let stripped = trimmed.strip_suffix("%").unwrap_or(trimmed);
+            stripped.parse().unwrap_or(DEFAULT_VOLUME)
         } else {
-            fs::write(&volume, "100").await?;
-            100u16
+            fs::write(&volume, DEFAULT_VOLUME.to_string()).await?;
+            DEFAULT_VOLUME
         };
 
-        Ok(InitialProperties { volume })
+        let keybinds = Keybinds::load(&config, args.vim_keys).await?;
+        let mut theme = Theme::load(&config).await?;
+        theme.border_style = args.border;
+        if !theme::supports_color() {
+            theme = theme.without_color();
+        }
+
+        // This is only written once the controls have been toggled while lowfi was running.
+        let minimalist = args.minimalist
+            || fs::read_to_string(config.join("minimalist.txt"))
+                .await
+                .is_ok_and(|x| x.trim() == "true");
+
+        Ok(InitialProperties {
+            volume,
+            keybinds,
+            theme,
+            minimalist,
+        })
     }
 
     /// Saves `volume.txt`, and uses the home directory which was previously acquired.
@@ -61,7 +103,15 @@ impl InitialProperties {
         let config = Self::config().await?;
         let path = config.join(PathBuf::from("volume.txt"));
 
-        fs::write(path, ((volume * 100.0).abs().round() as u16).to_string()).await?;
+        fs::write(path, ((volume * 100.0).round() as u16).to_string()).await?;
+
+        Ok(())
+    }
+
+    /// Saves `minimalist.txt`, so that the controls stay hidden or shown next time.
+    pub async fn save_minimalist(minimalist: bool) -> eyre::Result<()> {
+        let config = Self::config().await?;
+        fs::write(config.join("minimalist.txt"), minimalist.to_string()).await?;
 
         Ok(())
     }
@@ -71,20 +121,96 @@ impl InitialProperties {
 /// it when the frontend quits.
 pub async fn play(args: Args) -> eyre::Result<()> {
     // Load the initial properties (volume & config).
-    let properties = InitialProperties::load().await?;
+    let properties = InitialProperties::load(&args).await?;
+
+    // This is before the terminal is touched, so that any error is printed like usual.
+    #[cfg(unix)]
+    let ipc = args
+        .ipc
+        .as_deref()
+        .map(crate::player::ipc::bind)
+        .transpose()?;
+
+    // The terminal is set up before the player, so that there's
+    // something on screen while the track list is being fetched.
+    let environment = ui::Environment::ready(
+        args.alternate,
+        !args.no_keyboard_enhancement,
+        args.pause_on_unfocus,
+        args.headless,
+        #[cfg(unix)]
+        args.ipc.clone(),
+        args.print_on_exit,
+        args.quiet,
+    )?;
+
+    // The loading menu is dropped as soon as the player is ready, so it's never drawn over the interface.
+    // Quitting from it or the countdown means nothing ever plays, and the environment cleans up when it's dropped.
+    let player = Player::new(!args.alternate, &args);
+    let player = if args.headless {
+        player.await?
+    } else {
+        let loading = ui::loading(
+            ui::max_width(&args),
+            properties.minimalist,
+            &properties.theme,
+            &properties.keybinds,
+        );
+
+        tokio::select! {
+            player = player => player?,
+            quit = loading => return quit,
+        }
+    };
+    if args.countdown > 0 && !args.headless {
+        let started = ui::countdown(
+            args.countdown,
+            ui::max_width(&args),
+            properties.minimalist,
+            &properties.theme,
+            &properties.keybinds,
+        )
+        .await?;
+
+        if !started {
+            return Ok(());
+        }
+    }
 
+    let explicit = args.volume;
+    let minimalist = properties.minimalist;
     let (tx, rx) = mpsc::channel(8);
-    let player = Arc::new(Player::new(!args.alternate, &args).await?);
-    let ui = task::spawn(ui::start(Arc::clone(&player), tx.clone(), args));
+    let player = Arc::new(player);
+    let ui = task::spawn(ui::start(
+        Arc::clone(&player),
+        tx.clone(),
+        args,
+        properties.keybinds.clone(),
+        properties.theme.clone(),
+        environment,
+        #[cfg(unix)]
+        ipc,
+    ));
 
     tx.send(Messages::Init).await?;
 
-    Player::play(Arc::clone(&player), properties, tx.clone(), rx).await?;
+    // Even if playing fails, the volume should still be saved before the error is reported.
+    let played = Player::play(Arc::clone(&player), properties, tx.clone(), rx).await;
 
-    // Save the volume.txt file for the next session.
-    InitialProperties::save_volume(player.sink.volume()).await?;
+    // Stop the sink first, so that any last-second volume changes aren't audible.
     player.sink.stop();
+
+    // Save the volume.txt file for the next session, unless it's still just the one from `--volume`.
+    let volume = player.usual_volume();
+    if explicit != Some((volume * 100.0).round() as u16) {
+        InitialProperties::save_volume(volume).await?;
+    }
+
+    let toggled = player.minimalist.load(Ordering::Relaxed);
+    if toggled != minimalist {
+        InitialProperties::save_minimalist(toggled).await?;
+    }
     ui.abort();
 
-    Ok(())
+    played
 }
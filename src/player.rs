@@ -2,40 +2,82 @@
 //! This also has the code for the underlying
 //! audio server which adds new tracks.
 
-use std::{collections::VecDeque, sync::Arc, time::Duration};
+use std::{
+    collections::{HashMap, VecDeque},
+    path::PathBuf,
+    sync::{
+        atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering},
+        Arc,
+    },
+    time::{Duration, Instant},
+};
 
-use arc_swap::ArcSwapOption;
+use arc_swap::{ArcSwap, ArcSwapOption};
 use downloader::Downloader;
+use eyre::{bail, ensure, WrapErr};
 use libc::freopen;
+use queue::{Queued, Signals, State, Tracked};
 use reqwest::Client;
-use rodio::{OutputStream, OutputStreamHandle, Sink};
+use rodio::{
+    decoder::DecoderError, source::SeekError, OutputStream, OutputStreamHandle, Sample, Sink,
+    Source,
+};
 use tokio::{
+    io::AsyncWriteExt,
     select,
     sync::{
         mpsc::{Receiver, Sender},
-        RwLock,
+        Mutex, Notify, RwLock, Semaphore,
     },
-    task,
+    task::{self, JoinHandle},
 };
 
 use crate::{
     play::InitialProperties,
-    tracks::{DecodedTrack, Track, TrackInfo},
+    tracks::{
+        cache::Cache,
+        list::{self, List, Progress},
+        stream::{Underruns, PREBUFFER},
+        Data, DecodedTrack, Track, TrackInfo,
+    },
     Args,
 };
 
+pub mod devices;
 pub mod downloader;
+pub mod history;
+pub mod hook;
+#[cfg(unix)]
+pub mod ipc;
+pub mod queue;
+pub mod saved;
 pub mod ui;
 
 #[cfg(feature = "mpris")]
 pub mod mpris;
 
+#[cfg(feature = "notify")]
+pub mod notify;
+
+#[cfg(any(feature = "scrobble", feature = "listenbrainz"))]
+pub mod scrobbler;
+
+#[cfg(feature = "discord")]
+pub mod discord;
+
+#[cfg(feature = "visualizer")]
+pub mod visualizer;
+
 /// Handles communication between the frontend & audio player.
 #[derive(PartialEq, Debug, Clone, Copy)]
 pub enum Messages {
     /// Notifies the audio server that it should update the track.
     Next,
 
+    /// Goes back to the track which played before the current one,
+    /// or restarts the current one if it's been playing for a little while.
+    Previous,
+
     /// Special in that this isn't sent in a "client to server" sort of way,
     /// but rather is sent by a child of the server when a song has not only
     /// been requested but also downloaded aswell.
@@ -51,40 +93,354 @@ pub enum Messages {
     /// Pauses the [Sink]. This will also unpause it if it is paused.
     PlayPause,
 
+    /// Unpauses the [Sink], but unlike [Messages::PlayPause] it does nothing if it's already playing.
+    Play,
+
+    /// Pauses the [Sink], but unlike [Messages::PlayPause] it does nothing if it's already paused.
+    Pause,
+
     /// Change the volume of playback.
     ChangeVolume(f32),
 
+    /// Sets the volume of playback directly, instead of relative to the current volume.
+    /// This is clamped between 0 & `--max-volume`, just like [Messages::ChangeVolume].
+    SetVolume(f32),
+
+    /// Mutes the [Sink], or unmutes it & restores the prior volume if it's already muted.
+    ToggleMute,
+
+    /// Seeks within the current track, by an amount of seconds
+    /// relative to the current position.
+    Seek(i64),
+
+    /// Adds the current track to `bookmarks.txt`.
+    Bookmark,
+
+    /// Saves the current volume for the current track, which has to be bookmarked,
+    /// so that it's played at that volume from then on.
+    RememberVolume,
+
+    /// Adds the current track to `blocklist.txt`, and then skips it.
+    Block,
+
+    /// Toggles whether the current track should be repeated once it's over.
+    ToggleRepeat,
+
+    /// Toggles between picking tracks randomly & playing the list in order.
+    ToggleShuffle,
+
+    /// Shows or hides the list of keybindings in the UI.
+    ToggleHelp,
+
+    /// Shows or hides the controls at the bottom of the UI, like `--minimalist`.
+    ToggleControls,
+
+    /// Sent when the current track is almost over and crossfading is enabled,
+    /// which starts the next track while the current one fades out.
+    Crossfade,
+
+    /// Sent instead of [Messages::Next] when a track ends while repeating,
+    /// which will play the current track again from the start.
+    Replay,
+
+    /// Sent when the track which was queued up after the current one has started playing,
+    /// which happens right as the current one ends without any gap in between.
+    Advance,
+
+    /// Plays the current track from the start again, without counting it as a new listen.
+    Restart,
+
+    /// Seeks to an exact position within the current track.
+    /// This is only sent by MPRIS, since the UI only seeks relatively.
+    #[cfg(feature = "mpris")]
+    SetPosition(Duration),
+
     /// Quits gracefully.
     Quit,
 }
 
+/// How the volume shown in the UI is turned into the actual gain of the [Sink].
+#[derive(clap::ValueEnum, PartialEq, Debug, Clone, Copy)]
+pub enum VolumeCurve {
+    /// The gain is the same as the volume.
+    Linear,
+
+    /// The volume is in decibels, which is closer to how loudness is heard.
+    /// Anything above 100% is still linear, so that `--max-volume` means the same thing.
+    Log,
+}
+
+impl VolumeCurve {
+    /// How many decibels quieter the lowest volume (that isn't silent) is than 100%.
+    const RANGE: f32 = 50.0;
+
+    /// Gets the gain for the sink at `volume`.
+    fn gain(self, volume: f32) -> f32 {
+        match self {
+            Self::Log if volume > 0.0 && volume < 1.0 => {
+                10f32.powf(Self::RANGE * (volume - 1.0) / 20.0)
+            }
+            _ => volume,
+        }
+    }
+}
+
 const TIMEOUT: Duration = Duration::from_secs(8);
 
-/// The amount of songs to buffer up.
-const BUFFER_SIZE: usize = 5;
+/// How often [rodio] updates the position of the [Sink].
+/// This is used to know when a seek will actually show up.
+const SINK_UPDATE: Duration = Duration::from_millis(5);
+
+/// How many times lowfi will pick another track if it was played recently.
+const REROLLS: usize = 8;
+
+/// How far into a track the next one will be prefetched, as a fraction of its duration.
+const PREFETCH_POINT: f32 = 0.5;
+
+/// When the next track will be prefetched if the duration of the current one isn't known.
+const PREFETCH_FALLBACK: Duration = Duration::from_secs(30);
+
+/// How many steps a fade out is split into.
+const FADE_STEPS: u32 = 60;
+
+/// How long it takes for the volume to reach a new value after it's been changed,
+/// which is a tiny bit smoother than jumping straight to it, since that can click.
+const VOLUME_RAMP: Duration = Duration::from_millis(80);
+
+/// How many steps [VOLUME_RAMP] is split into.
+const RAMP_STEPS: u32 = 8;
+
+/// How often the output devices are checked with `--pause-on-unplug`.
+const DEVICE_POLL: Duration = Duration::from_secs(2);
+
+/// How often the sink is checked to make sure that the output stream is still playing it.
+const STALL_POLL: Duration = Duration::from_secs(2);
+
+/// How many times in a row the output stream can stall before lowfi stops watching it,
+/// since at that point the device is most likely gone for good.
+const MAX_STALLS: usize = 3;
+
+/// How long messages from [Player::flash] are displayed for.
+const FLASH_DURATION: Duration = Duration::from_secs(2);
+
+/// How many tracks in a row can fail to decode before lowfi gives up,
+/// since at that point it's probably not just a corrupt file.
+const MAX_FAILURES: usize = 5;
+
+/// How many of the tracks which played before the current one are kept for [Messages::Previous].
+const PREVIOUS_LENGTH: usize = 8;
+
+/// How far into a track [Messages::Previous] will restart it instead of going back.
+const PREVIOUS_THRESHOLD: Duration = Duration::from_secs(3);
 
 /// Main struct responsible for queuing up & playing tracks.
 pub struct Player {
     /// [rodio]'s [`Sink`] which can control playback.
     pub sink: Sink,
 
+    /// A second sink, which only plays the end of the previous track while crossfading.
+    fader: Sink,
+
+    /// How long to fade in for when the first track starts, and to fade out for when quitting.
+    fade: Duration,
+
+    /// Whether the first track has started yet, which is when lowfi fades in.
+    started: AtomicBool,
+
+    /// The volume from before the current fade out, if there is one.
+    fading: std::sync::Mutex<Option<f32>>,
+
+    /// How long to crossfade between tracks for, which is zero if crossfading is disabled.
+    crossfade: Duration,
+
+    /// Whether the output devices should be watched, so that lowfi can pause when one is unplugged.
+    pause_on_unplug: bool,
+
+    /// Whether alsa should be silenced when touching the audio devices.
+    silent: bool,
+
+    /// The largest that `history.tsv` can get, in bytes, before it's trimmed.
+    history: u64,
+
+    /// The highest volume allowed, which is above 1.0 with `--max-volume`.
+    max_volume: f32,
+
+    /// The volume as it's shown in the UI, which is turned into the gain of the sink with `curve`.
+    level: ArcSwap<f32>,
+
+    /// How `level` is turned into the gain of the sink, from `--volume-curve`.
+    curve: VolumeCurve,
+
+    /// The task which is gradually moving the gain of the sink towards `level`, see [Player::ramp_volume].
+    ramp: std::sync::Mutex<Option<JoinHandle<()>>>,
+
+    /// The volumes that bookmarked tracks have been given, from [saved::VOLUMES].
+    volumes: std::sync::RwLock<HashMap<String, f32>>,
+
+    /// Whether the current track is being played at its own volume from `volumes`.
+    overridden: AtomicBool,
+
+    /// The volume which tracks without their own volume are played at,
+    /// and which is restored once a track with its own volume is over.
+    usual: ArcSwap<f32>,
+
+    /// Whether each track's loudness should be normalized, see [Track::gain].
+    normalize: bool,
+
+    /// How much silence there is at the end of each track, from `--gap`.
+    gap: Duration,
+
+    /// The task which will start the crossfade at the end of the current track.
+    crossfader: std::sync::Mutex<Option<JoinHandle<eyre::Result<()>>>>,
+
     /// The [`TrackInfo`] of the current track.
     /// This is [`None`] when lowfi is buffering/loading.
     pub current: ArcSwapOption<TrackInfo>,
 
+    /// The [`TrackInfo`] of the most recent track, which unlike `current`, is kept while loading.
+    last: ArcSwapOption<TrackInfo>,
+
+    /// How many tracks have started playing so far, including repeats.
+    pub played: AtomicUsize,
+
     /// This is the MPRIS server, which is initialized later on in the
     /// user interface.
     #[cfg(feature = "mpris")]
     pub mpris: tokio::sync::OnceCell<mpris_server::Server<mpris::Player>>,
 
+    /// The volume from before the [Sink] was muted.
+    /// This is [`None`] when lowfi isn't muted.
+    muted: ArcSwapOption<f32>,
+
+    /// Sends a desktop notification when the track changes.
+    /// This is [`None`] if `--notify` wasn't specified.
+    #[cfg(feature = "notify")]
+    notifier: Option<notify::Notifier>,
+
+    /// The command which is ran whenever a new track starts, from `--on-track`.
+    on_track: Option<String>,
+
+    /// Submits listened tracks to Last.fm.
+    /// This is [`None`] if scrobbling hasn't been set up in `scrobble.toml`.
+    #[cfg(any(feature = "scrobble", feature = "listenbrainz"))]
+    scrobbler: Option<Arc<scrobbler::Scrobbler>>,
+
+    /// Shows the current track on Discord.
+    /// This is [`None`] if it hasn't been set up in `discord.toml`.
+    #[cfg(feature = "discord")]
+    presence: Option<discord::Presence>,
+
+    /// Analyzes whatever's playing, so that the UI can show it.
+    /// This is [`None`] if `--visualizer` wasn't specified.
+    #[cfg(feature = "visualizer")]
+    pub visualizer: Option<Arc<visualizer::Visualizer>>,
+
+    /// The identifiers of the most recently picked tracks, which shouldn't be picked again yet.
+    recent: std::sync::Mutex<VecDeque<String>>,
+
+    /// The maximum length of `recent`.
+    window: usize,
+
+    /// The index of the track from `--start`, which is played before anything else.
+    start: std::sync::Mutex<Option<usize>>,
+
+    /// The next track, which has already been taken out of the buffer & decoded
+    /// so that it can be played as soon as the current one is over.
+    prefetched: Mutex<Option<(Track, DecodedTrack)>>,
+
+    /// The task which will prefetch the next track partway through the current one.
+    prefetcher: std::sync::Mutex<Option<JoinHandle<()>>>,
+
+    /// The raw data of the current track, which is kept around so it can be repeated.
+    playing: ArcSwapOption<Track>,
+
+    /// The download of the next track, while [Player::next] is waiting on it.
+    progress: ArcSwapOption<Progress>,
+
+    /// The raw data of the tracks which played before the current one, with the latest at the back.
+    previous: std::sync::Mutex<VecDeque<Track>>,
+
+    /// Lets the player know when each of the sources in the sink starts & finishes.
+    signals: Arc<Signals>,
+
+    /// The identifier of the source in the sink which belongs to the current track.
+    source: AtomicU64,
+
+    /// The silence that the current track has played while waiting for its download.
+    underruns: std::sync::Mutex<Option<Underruns>>,
+
+    /// The track which has already been appended to the sink after the current one.
+    /// This is always [None] while crossfading, since that uses [Player::fader] instead.
+    queued: std::sync::Mutex<Option<Queued>>,
+
+    /// Whether the current track should be played again once it's over.
+    pub repeat: AtomicBool,
+
+    /// Whether the current track supports seeking.
+    /// This is only known to be false once a seek has actually failed.
+    pub seekable: AtomicBool,
+
+    /// Whether the UI is showing the list of keybindings instead of the usual menu.
+    pub help: AtomicBool,
+
+    /// Whether the controls are hidden, which starts off as `--minimalist` but can be toggled.
+    pub minimalist: AtomicBool,
+
+    /// Whether lowfi should quit after the first track, from `--once`.
+    once: bool,
+
+    /// Whether playback was paused because the output stream stopped, like after a suspend.
+    /// This is cleared once playback is resumed.
+    pub stalled: AtomicBool,
+
+    /// Whether the next track is still being downloaded or decoded.
+    /// This is only cleared once the track has actually started playing.
+    pub loading: AtomicBool,
+
+    /// How many tracks in a row have failed to decode.
+    failures: AtomicUsize,
+
+    /// The file that errors are appended to, from `--log`.
+    log: Option<PathBuf>,
+
+    /// The base URL that tracks were last downloaded from, which is logged whenever it changes.
+    mirror: std::sync::Mutex<Option<String>>,
+
+    /// How long lowfi has played for before it was last paused, alongside
+    /// when it was resumed, which is [None] while paused.
+    listened: std::sync::Mutex<(Duration, Option<Instant>)>,
+
+    /// What [Player::listened] was when the current track started, see [Player::played].
+    track_start: std::sync::Mutex<Duration>,
+
+    /// A short message to display in the UI, alongside when it was shown.
+    flash: std::sync::Mutex<Option<(&'static str, Instant)>>,
+
+    /// Used to tell the user interface that it should redraw
+    /// right away, instead of waiting for the next frame.
+    pub redraw: Notify,
+
     /// The tracks, which is a [VecDeque] that holds
     /// *undecoded* [Track]s.
     tracks: RwLock<VecDeque<Track>>,
 
+    /// The amount of tracks to buffer up, from `--buffer`.
+    buffer: usize,
+
+    /// Limits how many tracks can be downloading at once, which is never more than `buffer`.
+    downloads: Arc<Semaphore>,
+
+    /// The list of tracks which new tracks are picked from.
+    list: List,
+
     /// The web client, which can contain a UserAgent & some
     /// settings that help lowfi work more effectively.
     client: Client,
 
+    /// Like [Player::client], but for streaming, where a response lasts as long as the track does.
+    /// Instead of a total timeout, this only times out once the server stops sending anything.
+    streamer: Client,
+
     /// The [OutputStreamHandle], which also can control some
     /// playback, is for now unused and is here just to keep it
     /// alive so the playback can function properly.
@@ -95,6 +451,30 @@ pub struct Player {
     _stream: OutputStream,
 }
 
+/// Shows the progress of a download in [Player::progress], for as long as it's being waited on.
+///
+/// This is cleared once it's dropped, even if that's because [Player::next] was cancelled.
+struct Waiting<'a> {
+    slot: &'a ArcSwapOption<Progress>,
+    progress: Arc<Progress>,
+}
+
+impl<'a> Waiting<'a> {
+    fn new(slot: &'a ArcSwapOption<Progress>) -> Self {
+        let progress = Arc::new(Progress::default());
+        slot.store(Some(Arc::clone(&progress)));
+
+        Self { slot, progress }
+    }
+}
+
+impl Drop for Waiting<'_> {
+    fn drop(&mut self) {
+        // Something else might be waiting by now, in which case that's left alone.
+        self.slot.compare_and_swap(&self.progress, None);
+    }
+}
+
 /// SAFETY: This is necessary because [OutputStream] does not implement [Send],
 /// SAFETY: even though it is perfectly possible.
 unsafe impl Send for Player {}
@@ -103,8 +483,10 @@ unsafe impl Send for Player {}
 unsafe impl Sync for Player {}
 
 impl Player {
-    /// This gets the output stream while also shutting up alsa with [libc].
-    fn silent_get_output_stream() -> eyre::Result<(OutputStream, OutputStreamHandle)> {
+    /// Runs `f` while also shutting up alsa with [libc].
+    ///
+    /// This is needed for anything that touches the audio devices.
+    pub fn silently<T>(f: impl FnOnce() -> T) -> T {
         extern "C" {
             static stderr: *mut libc::FILE;
         }
@@ -117,29 +499,326 @@ impl Player {
         // SAFETY: Simple enough to be impossible to fail. Hopefully.
         unsafe { freopen(c"/dev/null".as_ptr(), mode, stderr) };
 
-        let (stream, handle) = OutputStream::try_default()?;
+        let result = f();
 
         // SAFETY: See the first call to `freopen`.
         unsafe { freopen(c"/dev/tty".as_ptr(), mode, stderr) };
 
-        Ok((stream, handle))
+        result
+    }
+
+    /// Gets the output stream, either for the device matching `device` or the default one.
+    fn output_stream(device: Option<&str>) -> eyre::Result<(OutputStream, OutputStreamHandle)> {
+        Ok(match device {
+            Some(name) => OutputStream::try_from_device(&devices::find(name)?)?,
+            None => OutputStream::try_default()?,
+        })
     }
 
     /// Just a shorthand for setting `current`.
+    ///
+    /// This also tells any MPRIS clients that the metadata has changed, alongside
+    /// whether there's a previous track, since that only changes with the current track.
     async fn set_current(&self, info: TrackInfo) -> eyre::Result<()> {
-        self.current.store(Some(Arc::new(info)));
+        self.apply_volume(&info.id);
+        self.reset_played();
+
+        let info = Arc::new(info);
+        self.current.store(Some(Arc::clone(&info)));
+        self.last.store(Some(info));
+        self.played.fetch_add(1, Ordering::Relaxed);
+        self.seekable.store(true, Ordering::Relaxed);
+        self.redraw.notify_one();
+
+        #[cfg(feature = "mpris")]
+        if let Some(server) = self.mpris.get() {
+            let metadata = mpris::metadata(self.current.load().as_deref());
+
+            // Errors are ignored.
+            let _ = server
+                .properties_changed([
+                    mpris_server::Property::Metadata(metadata),
+                    mpris_server::Property::PlaybackStatus(mpris::playback_status(self)),
+                    mpris_server::Property::CanSeek(true),
+                    mpris_server::Property::CanGoNext(true),
+                    mpris_server::Property::CanGoPrevious(self.has_previous()),
+                ])
+                .await;
+        }
 
         Ok(())
     }
 
+    /// Tells MPRIS clients whether lowfi is playing, paused, or stopped while the next track loads,
+    /// which is needed for desktop media controls to stay in sync.
+    #[cfg(feature = "mpris")]
+    async fn playback_changed(&self) {
+        if let Some(server) = self.mpris.get() {
+            let status = mpris::playback_status(self);
+
+            let _ = server
+                .properties_changed([mpris_server::Property::PlaybackStatus(status)])
+                .await;
+        }
+    }
+
+    /// Gets the track which is playing, or the one that played last if the next one is still loading.
+    pub fn last_played(&self) -> Option<Arc<TrackInfo>> {
+        self.last.load_full()
+    }
+
+    /// Whether there's a track in [Player::previous] to go back to.
+    #[cfg(feature = "mpris")]
+    pub fn has_previous(&self) -> bool {
+        !self.previous.lock().unwrap().is_empty()
+    }
+
+    /// How far into the current track playback has gotten.
+    ///
+    /// This is the sink's position, but without any silence from underruns, since that isn't part of the track.
+    pub fn elapsed(&self) -> Duration {
+        let position = self.sink.get_pos();
+        match &*self.underruns.lock().unwrap() {
+            Some(underruns) => position.saturating_sub(underruns.duration()),
+            None => position,
+        }
+    }
+
     /// A shorthand for checking if `self.current` is [Some].
     pub fn current_exists(&self) -> bool {
         self.current.load().is_some()
     }
 
-    /// Sets the volume of the sink, and also clamps the value to avoid negative/over 100% values.
+    /// Gets the index of the current track in the list, alongside how many tracks there are.
+    ///
+    /// This is [None] if nothing is playing.
+    pub fn position(&self) -> Option<(usize, usize)> {
+        let index = self.current.load().as_ref()?.index;
+        Some((index, self.list.len()))
+    }
+
+    /// Gets how long lowfi has been playing for, not counting any time spent paused.
+    pub fn listened(&self) -> Duration {
+        let (listened, resumed) = *self.listened.lock().unwrap();
+        listened + resumed.map_or(Duration::ZERO, |x| x.elapsed())
+    }
+
+    /// Gets how long the current track has actually been heard for, which unlike
+    /// the position of the sink, doesn't count seeking forward as listening.
+    fn played(&self) -> Duration {
+        self.listened()
+            .saturating_sub(*self.track_start.lock().unwrap())
+    }
+
+    /// Starts counting [Player::played] again from zero, since a new track has just started.
+    fn reset_played(&self) {
+        *self.track_start.lock().unwrap() = self.listened();
+    }
+
+    /// Updates [Player::listened], which has to happen whenever the sink is paused or resumed.
+    fn update_listened(&self) {
+        let mut listened = self.listened.lock().unwrap();
+        if let Some(resumed) = listened.1.take() {
+            listened.0 += resumed.elapsed();
+        }
+
+        if !self.sink.is_paused() {
+            listened.1 = Some(Instant::now());
+        }
+    }
+
+    /// Briefly shows `message` in the UI, replacing any message which is already being shown.
+    pub fn flash(&self, message: &'static str) {
+        *self.flash.lock().unwrap() = Some((message, Instant::now()));
+        self.redraw.notify_one();
+    }
+
+    /// Gets the message from [Player::flash], if it should still be shown.
+    pub fn flashed(&self) -> Option<&'static str> {
+        self.flash
+            .lock()
+            .unwrap()
+            .filter(|(_, shown)| shown.elapsed() < FLASH_DURATION)
+            .map(|(message, _)| message)
+    }
+
+    /// Sets the volume of the sink, and also clamps the value to avoid negative values or ones over `--max-volume`.
+    ///
+    /// This will also unmute the sink if it was muted. The gain of the
+    /// sink might not actually be `volume`, depending on `--volume-curve`.
     pub fn set_volume(&self, volume: f32) {
-        self.sink.set_volume(volume.clamp(0.0, 1.0))
+        self.stop_ramp();
+
+        let gain = self.set_level(volume);
+        self.sink.set_volume(gain);
+        self.fader.set_volume(gain);
+    }
+
+    /// Changes the volume just like [Player::set_volume], except that the gain of
+    /// the sink moves towards it over [VOLUME_RAMP], instead of jumping straight there.
+    ///
+    /// The UI shows the new volume right away, and if the volume is changed again
+    /// during the ramp, then the new ramp just starts from wherever the old one got to.
+    fn ramp_volume(player: &Arc<Self>, volume: f32) {
+        let from = player.sink.volume();
+        let to = player.set_level(volume);
+
+        let clone = Arc::clone(player);
+        let ramp = task::spawn(async move {
+            for step in 1..=RAMP_STEPS {
+                let gain = from + (to - from) * step as f32 / RAMP_STEPS as f32;
+                clone.sink.set_volume(gain);
+                clone.fader.set_volume(gain);
+
+                tokio::time::sleep(VOLUME_RAMP / RAMP_STEPS).await;
+            }
+        });
+
+        if let Some(previous) = player.ramp.lock().unwrap().replace(ramp) {
+            previous.abort();
+        }
+    }
+
+    /// Stops any ramp from [Player::ramp_volume], so that it doesn't override a change to the gain.
+    fn stop_ramp(&self) {
+        if let Some(ramp) = self.ramp.lock().unwrap().take() {
+            ramp.abort();
+        }
+    }
+
+    /// Clamps `volume` & stores it as the volume that's shown in the UI, which also unmutes the sink.
+    /// This returns the gain of the sink for `volume`, but the sink itself is left alone.
+    fn set_level(&self, volume: f32) -> f32 {
+        let volume = volume.clamp(0.0, self.max_volume);
+        self.level.store(Arc::new(volume));
+        self.muted.store(None);
+
+        self.curve.gain(volume)
+    }
+
+    /// Gets the volume as it's shown in the UI, which, if lowfi is muted, is the volume from before the mute.
+    pub fn volume(&self) -> f32 {
+        **self.level.load()
+    }
+
+    /// Gets the volume which tracks without their own volume are played at,
+    /// which is the one that should be saved for the next session.
+    pub fn usual_volume(&self) -> f32 {
+        if self.overridden.load(Ordering::Relaxed) {
+            **self.usual.load()
+        } else {
+            self.volume()
+        }
+    }
+
+    /// Switches to the volume that the track with `id` was given, if it has one,
+    /// or otherwise back to the usual volume if the last track had its own.
+    fn apply_volume(&self, id: &str) {
+        let volume = self.volumes.read().unwrap().get(id).copied();
+        let volume = match volume {
+            Some(volume) => {
+                if !self.overridden.swap(true, Ordering::Relaxed) {
+                    self.usual.store(Arc::new(self.volume()));
+                }
+
+                volume
+            }
+            None if self.overridden.swap(false, Ordering::Relaxed) => **self.usual.load(),
+            None => {
+                self.usual.store(Arc::new(self.volume()));
+                return;
+            }
+        };
+
+        // Setting the volume would unmute the sink, so it's muted again afterwards.
+        let muted = self.is_muted();
+        self.set_volume(volume);
+        if muted {
+            self.toggle_mute();
+        }
+
+        ui::volume_changed();
+    }
+
+    /// Gets how many bytes of the download which is being waited on have arrived,
+    /// alongside its size if it's known.
+    ///
+    /// This is [None] if nothing is being waited on, or the server hasn't responded yet.
+    pub fn progress(&self) -> Option<(u64, Option<u64>)> {
+        self.progress.load().as_ref().and_then(|x| x.get())
+    }
+
+    /// A shorthand for checking if lowfi is currently muted.
+    pub fn is_muted(&self) -> bool {
+        self.muted.load().is_some()
+    }
+
+    /// Mutes the sink, or if it's already muted, restores the volume from before.
+    pub fn toggle_mute(&self) {
+        self.stop_ramp();
+        match self.muted.swap(None) {
+            Some(volume) => self.sink.set_volume(self.curve.gain(*volume)),
+            None => {
+                self.muted.store(Some(Arc::new(self.volume())));
+                self.sink.set_volume(0.0);
+            }
+        }
+
+        self.fader.set_volume(self.sink.volume());
+    }
+
+    /// Gradually fades out the volume over `duration`, and then pauses the sink.
+    ///
+    /// The sink is paused rather than stopped, since stopping it would make the
+    /// play loop move on to the next track before it gets the chance to quit.
+    /// The volume is restored afterwards, so that the fade won't end up saved in `volume.txt`.
+    ///
+    /// If another fade is already running, then it's cut short, and if nothing
+    /// can be heard anyway, then the fade is skipped.
+    pub async fn fade_out(&self, duration: Duration) {
+        {
+            let mut fading = self.fading.lock().unwrap();
+            if let Some(volume) = fading.take() {
+                self.finish_fade(volume);
+                return;
+            }
+
+            if self.is_muted() || self.sink.is_paused() || self.sink.empty() {
+                return;
+            }
+
+            // The fade should start from the volume that a ramp would've ended up at.
+            self.stop_ramp();
+            self.sink.set_volume(self.curve.gain(self.volume()));
+            *fading = Some(self.sink.volume());
+        }
+
+        for step in (0..FADE_STEPS).rev() {
+            {
+                let fading = self.fading.lock().unwrap();
+                let Some(volume) = *fading else {
+                    return;
+                };
+
+                let level = volume * step as f32 / FADE_STEPS as f32;
+                self.sink.set_volume(level);
+                self.fader.set_volume(level);
+            }
+
+            tokio::time::sleep(duration / FADE_STEPS).await;
+        }
+
+        if let Some(volume) = self.fading.lock().unwrap().take() {
+            self.finish_fade(volume);
+        }
+    }
+
+    /// Pauses both sinks after a fade out, and then restores their `volume`.
+    fn finish_fade(&self, volume: f32) {
+        self.sink.pause();
+        self.fader.pause();
+        self.sink.set_volume(volume);
+        self.fader.set_volume(volume);
     }
 
     /// Initializes the entire player, including audio devices & sink.
@@ -148,10 +827,98 @@ impl Player {
     /// but this option is only applicable on Linux, as on MacOS & Windows
     /// it will never be silent.
     pub async fn new(silent: bool, args: &Args) -> eyre::Result<Self> {
-        let (_stream, handle) = if silent && cfg!(target_os = "linux") && !args.debug {
-            Self::silent_get_output_stream()?
+        let agent = concat!(env!("CARGO_PKG_NAME"), "/", env!("CARGO_PKG_VERSION"));
+        let client = Client::builder()
+            .user_agent(agent)
+            .timeout(TIMEOUT)
+            .build()?;
+        let streamer = Client::builder()
+            .user_agent(agent)
+            .connect_timeout(TIMEOUT)
+            .read_timeout(TIMEOUT)
+            .build()?;
+
+        let cache = if args.no_cache {
+            None
+        } else {
+            Some(Cache::new(args.cache_size).await?)
+        };
+
+        // How many entries in the `--playlist` couldn't be played, which is flashed once lowfi starts.
+        let mut skipped = 0;
+        let list = match (&args.dir, &args.playlist, args.tracks.as_slice()) {
+            _ if args.bookmarks => List::from_file(&saved::path(saved::BOOKMARKS).await?).await?,
+            (Some(dir), _, _) => List::from_dir(dir).await?,
+            (None, Some(playlist), _) => {
+                let (list, count) = List::from_playlist(playlist).await?;
+                skipped = count;
+                list
+            }
+            (None, None, []) => List::builtin(),
+            (None, None, urls) => List::from_urls(urls.to_vec(), &client, cache.as_ref()).await?,
+        };
+        let list = match cache {
+            Some(cache) => list.with_cache(cache),
+            None => list,
+        };
+        let list = if args.raw_names {
+            list.with_raw_names()
+        } else {
+            list
+        };
+        let list = list.with_at_end(args.at_end);
+
+        // Without a network, only the tracks which have already been downloaded can be played.
+        let list = if list.is_online(&client).await {
+            list
+        } else {
+            list.offline()?
+        };
+
+        let list = match &args.filter {
+            Some(filter) => list.with_filter(filter)?,
+            None => list,
+        };
+        let list = list.with_blocklist(&saved::read(saved::BLOCKLIST).await?)?;
+        list.set_shuffle(!args.sequential);
+
+        // Bookmarks might've been removed by hand, and only they can have their own volume.
+        let bookmarks = saved::read(saved::BOOKMARKS).await?;
+        let mut volumes = saved::volumes().await?;
+        volumes.retain(|id, _| bookmarks.contains(id));
+
+        // The window can't cover the whole list, since then every track would be rejected.
+        let window = args.no_repeat_window.min(list.len().saturating_sub(1));
+
+        // If nothing matches, lowfi just starts like usual. The interface
+        // is already up by now, so this is flashed instead of printed.
+        let start = args.start.as_ref().and_then(|query| list.find(query));
+        let missing = args.start.is_some() && start.is_none();
+
+        // The rest of the list is played after the starting track when playing in order.
+        let recent: VecDeque<String> = start.iter().map(|x| list.id(list.get(*x))).collect();
+        if let Some(id) = recent.front() {
+            list.continue_from(id);
+        }
+
+        #[cfg(any(feature = "scrobble", feature = "listenbrainz"))]
+        let scrobbler = scrobbler::Scrobbler::load(
+            &InitialProperties::config().await?,
+            client.clone(),
+            args.scrobble_threshold,
+            args.scrobble_min_seconds,
+        )
+        .await?
+        .map(Arc::new);
+
+        #[cfg(feature = "discord")]
+        let presence = discord::Presence::load(&InitialProperties::config().await?).await?;
+
+        let silent = silent && cfg!(target_os = "linux") && !args.debug;
+        let (_stream, handle) = if silent {
+            Self::silently(|| Self::output_stream(args.device.as_deref()))?
         } else {
-            OutputStream::try_default()?
+            Self::output_stream(args.device.as_deref())?
         };
 
         let sink = Sink::try_new(&handle)?;
@@ -159,40 +926,610 @@ impl Player {
             sink.pause();
         }
 
+        let fader = Sink::try_new(&handle)?;
+
+        // Only one track is ever needed with `--once`, so nothing is downloaded ahead of time.
+        let buffer = if args.once {
+            0
+        } else {
+            usize::from(args.buffer)
+        };
         let player = Self {
-            tracks: RwLock::new(VecDeque::with_capacity(5)),
+            tracks: RwLock::new(VecDeque::with_capacity(buffer)),
+            buffer,
+            downloads: Arc::new(Semaphore::new(buffer.max(1))),
+            list,
             current: ArcSwapOption::new(None),
-            client: Client::builder()
-                .user_agent(concat!(
-                    env!("CARGO_PKG_NAME"),
-                    "/",
-                    env!("CARGO_PKG_VERSION")
-                ))
-                .timeout(TIMEOUT)
-                .build()?,
+            last: ArcSwapOption::new(None),
+            played: AtomicUsize::new(0),
+            muted: ArcSwapOption::new(None),
+            client,
+            streamer,
             sink,
+            fader,
+            fade: Duration::from_millis(args.fade),
+            started: AtomicBool::new(false),
+            fading: std::sync::Mutex::new(None),
+            crossfade: Duration::from_secs(args.crossfade),
+            gap: Duration::from_secs(if args.crossfade == 0 { args.gap } else { 0 }),
+            crossfader: std::sync::Mutex::new(None),
+            normalize: args.normalize,
+            volumes: std::sync::RwLock::new(volumes),
+            overridden: AtomicBool::new(false),
+            usual: ArcSwap::new(Arc::new(1.0)),
+            pause_on_unplug: args.pause_on_unplug,
+            history: args.history_size * 1024,
+            max_volume: f32::from(args.max_volume) / 100.0,
+            level: ArcSwap::from_pointee(1.0),
+            curve: args.volume_curve,
+            ramp: std::sync::Mutex::new(None),
+            silent,
+            recent: std::sync::Mutex::new(recent),
+            window,
+            start: std::sync::Mutex::new(start),
+            prefetched: Mutex::new(None),
+            prefetcher: std::sync::Mutex::new(None),
+            playing: ArcSwapOption::new(None),
+            progress: ArcSwapOption::new(None),
+            previous: std::sync::Mutex::new(VecDeque::with_capacity(PREVIOUS_LENGTH)),
+            signals: Arc::new(Signals::default()),
+            source: AtomicU64::new(0),
+            underruns: std::sync::Mutex::new(None),
+            queued: std::sync::Mutex::new(None),
+            repeat: AtomicBool::new(false),
+            seekable: AtomicBool::new(true),
+            help: AtomicBool::new(false),
+            minimalist: AtomicBool::new(args.minimalist),
+            once: args.once,
+            stalled: AtomicBool::new(false),
+            loading: AtomicBool::new(true),
+            failures: AtomicUsize::new(0),
+            log: args.log.clone(),
+            mirror: std::sync::Mutex::new(None),
+            listened: std::sync::Mutex::new((Duration::ZERO, (!args.paused).then(Instant::now))),
+            track_start: std::sync::Mutex::new(Duration::ZERO),
+            flash: std::sync::Mutex::new(None),
+            redraw: Notify::new(),
             _handle: handle,
             _stream,
 
             #[cfg(feature = "mpris")]
             mpris: tokio::sync::OnceCell::new(),
+
+            #[cfg(feature = "notify")]
+            notifier: args.notify.then(notify::Notifier::new),
+            on_track: args.on_track.clone(),
+
+            #[cfg(any(feature = "scrobble", feature = "listenbrainz"))]
+            scrobbler,
+
+            #[cfg(feature = "discord")]
+            presence,
+
+            #[cfg(feature = "visualizer")]
+            visualizer: args
+                .visualizer
+                .then(|| Arc::new(visualizer::Visualizer::new())),
         };
 
+        if missing {
+            player.flash("couldn't find the --start track");
+        } else if skipped > 0 {
+            player.flash("skipped some broken playlist entries");
+        }
+
         Ok(player)
     }
 
-    /// This will play the next track, as well as refilling the buffer in the background.
-    pub async fn next(&self) -> eyre::Result<DecodedTrack> {
-        let track = match self.tracks.write().await.pop_front() {
-            Some(x) => x,
-            // If the queue is completely empty, then fallback to simply getting a new track.
-            // This is relevant particularly at the first song.
-            None => Track::random(&self.client).await?,
+    /// Picks the next track from the list and downloads it.
+    ///
+    /// This waits if `--buffer` tracks are already being downloaded.
+    ///
+    /// When shuffling, tracks which were picked recently will be re-rolled
+    /// up to [REROLLS] times, so that the same track isn't heard twice in a row.
+    /// If `stream` is set, then the track can start playing before it's fully downloaded.
+    /// How much of it has arrived is written to `progress`, and a streamed track
+    /// keeps counting towards `--buffer` until it's been completely downloaded.
+    pub async fn fetch(&self, stream: bool, progress: Arc<Progress>) -> eyre::Result<Track> {
+        let permit = Arc::clone(&self.downloads).acquire_owned().await?;
+
+        let track = {
+            let mut recent = self.recent.lock().unwrap();
+            let id = |index| self.list.id(self.list.get(index));
+
+            let Some(mut track) = self.list.next() else {
+                bail!("there aren't any tracks left in the list");
+            };
+
+            for _ in 0..REROLLS {
+                if !self.list.is_shuffled() || !recent.contains(&id(track)) {
+                    break;
+                }
+
+                if let Some(next) = self.list.next() {
+                    track = next;
+                }
+            }
+
+            recent.push_back(id(track));
+            while recent.len() > self.window {
+                recent.pop_front();
+            }
+
+            track
+        };
+
+        let track = if stream {
+            let (client, streamer) = (&self.client, &self.streamer);
+            Track::stream(&self.list, track, client, streamer, progress, permit).await
+        } else {
+            Track::download(&self.list, track, &self.client, &progress).await
         };
 
-        let decoded = track.decode()?;
+        // This is mostly useful for finding out when lowfi has switched to a mirror.
+        if let Some(mirror) = self.list.mirror() {
+            let mirror = mirror.to_string();
+            let previous = self.mirror.lock().unwrap().replace(mirror.clone());
+            if previous.as_ref() != Some(&mirror) {
+                self.log(&format!("downloading tracks from {mirror}")).await;
+            }
+        }
 
-        Ok(decoded)
+        track
+    }
+
+    /// Takes the next track out of the buffer, or downloads one if the buffer is empty.
+    ///
+    /// The track from `--start` always comes first, though. Since something is waiting on
+    /// these, they're streamed unless normalizing, which needs the whole track up front.
+    async fn pop(&self, progress: Arc<Progress>) -> eyre::Result<Track> {
+        let start = self.start.lock().unwrap().take();
+        if let Some(track) = start {
+            let permit = Arc::clone(&self.downloads).acquire_owned().await?;
+            return if self.normalize {
+                Track::download(&self.list, track, &self.client, &progress).await
+            } else {
+                let (client, streamer) = (&self.client, &self.streamer);
+                Track::stream(&self.list, track, client, streamer, progress, permit).await
+            };
+        }
+
+        match self.tracks.write().await.pop_front() {
+            Some(x) => Ok(x),
+            // If the queue is completely empty, then fallback to simply getting a new track.
+            // This is relevant particularly at the first song.
+            None => self.fetch(!self.normalize, progress).await,
+        }
+    }
+
+    /// This will play the next track, as well as refilling the buffer in the background.
+    ///
+    /// If the next track has already been queued up or prefetched, then that's used instead.
+    /// This should only be called once the sink has been stopped, since anything queued is taken.
+    pub async fn next(&self) -> eyre::Result<DecodedTrack> {
+        let queued = self.queued.lock().unwrap().take().filter(|x| !x.replay);
+        let queued = match queued {
+            Some(queued) => Some(queued.restore()?),
+            None => self.prefetched.lock().await.take(),
+        };
+
+        let (track, decoded) = match queued {
+            Some(x) => x,
+            None => {
+                // This also covers decoding, since a streamed track has to be buffered first.
+                let waiting = Waiting::new(&self.progress);
+                let track = self.pop(Arc::clone(&waiting.progress)).await?;
+                let decoded = self.decode(&track).await?;
+                drop(waiting);
+
+                (track, decoded)
+            }
+        };
+
+        self.playing.store(Some(Arc::new(track)));
+
+        Ok(decoded)
+    }
+
+    /// Plays `track` again, which was taken from [Player::previous].
+    ///
+    /// The current track is put back so that it plays afterwards, followed by whatever was
+    /// going to be next. Just like [Player::next], the sink has to have been stopped first.
+    async fn rewind(&self, track: Track) -> eyre::Result<DecodedTrack> {
+        let decoded = self.decode(&track).await?;
+
+        let queued = self.queued.lock().unwrap().take().filter(|x| !x.replay);
+        let mut prefetched = self.prefetched.lock().await;
+        let next = match queued {
+            Some(queued) => Some(queued.restore()?),
+            None => prefetched.take(),
+        };
+
+        // The buffer could be full already, in which case it just goes over `--buffer` by one
+        // for a bit, since throwing away a track that's already been downloaded would be a waste.
+        if let Some((next, _)) = next {
+            self.tracks.write().await.push_front(next);
+        }
+
+        if let Some(current) = self.playing.load_full() {
+            let decoded = self.decode(&current).await?;
+            *prefetched = Some(((*current).clone(), decoded));
+        }
+
+        drop(prefetched);
+        self.playing.store(Some(Arc::new(track)));
+
+        Ok(decoded)
+    }
+
+    /// Keeps the current track in [Player::previous], since something else is about to play.
+    fn remember(&self) {
+        let Some(track) = self.playing.load_full() else {
+            return;
+        };
+
+        // Blocked tracks shouldn't be coming back.
+        if self.list.is_blocked(&track.id) {
+            return;
+        }
+
+        let mut previous = self.previous.lock().unwrap();
+        previous.push_back((*track).clone());
+        while previous.len() > PREVIOUS_LENGTH {
+            previous.pop_front();
+        }
+    }
+
+    /// Decodes `track`, and also works out its gain if normalizing is enabled.
+    async fn decode(&self, track: &Track) -> eyre::Result<DecodedTrack> {
+        // Decoding reads the start of the track, which could block while it's still arriving,
+        // so it gets a head start, and then the decoder is set up where it's allowed to block.
+        let mut decoded = match &track.data {
+            Data::Streaming(stream) => {
+                stream.buffered(PREBUFFER).await;
+
+                let clone = track.clone();
+                task::spawn_blocking(move || Self::decode_only(&clone)).await??
+            }
+            Data::Full(_) => Self::decode_only(track)?,
+        };
+
+        if self.normalize {
+            let clone = track.clone();
+            decoded.info.gain = task::spawn_blocking(move || clone.gain())
+                .await?
+                .wrap_err_with(|| format!("couldn't decode {}", track.id))?;
+        }
+
+        Ok(decoded)
+    }
+
+    /// Just decodes `track`, which is the part of [Player::decode] that doesn't need the player.
+    fn decode_only(track: &Track) -> eyre::Result<DecodedTrack> {
+        // `Bytes` is reference counted, so this doesn't actually copy the data.
+        track
+            .clone()
+            .decode()
+            .wrap_err_with(|| format!("couldn't decode {}", track.id))
+    }
+
+    /// Whether `error` means that a track couldn't be decoded, in which case it's
+    /// most likely just corrupt, so it's skipped instead of being downloaded again.
+    fn is_undecodable(error: &eyre::Report) -> bool {
+        error.downcast_ref::<DecoderError>().is_some()
+    }
+
+    /// Appends `error` to the file from `--log` on a single line, if there is one.
+    async fn log_error(&self, error: &eyre::Report) {
+        let chain: Vec<String> = error.chain().map(|x| x.to_string()).collect();
+        self.log(&chain.join(": ")).await;
+    }
+
+    /// Appends `message` to the file from `--log` on a single line, if there is one.
+    ///
+    /// Errors are ignored.
+    async fn log(&self, message: &str) {
+        let Some(path) = &self.log else {
+            return;
+        };
+
+        let line = format!("{}\n", message.replace('\n', " "));
+
+        if let Ok(mut file) = tokio::fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)
+            .await
+        {
+            let _ = file.write_all(line.as_bytes()).await;
+        }
+    }
+
+    /// The gain of the current track, which is 1.0 if nothing is playing.
+    fn gain(&self) -> f32 {
+        self.current.load().as_ref().map_or(1.0, |x| x.gain)
+    }
+
+    /// Waits for `delay`, and then takes the next track out of the buffer & decodes it.
+    ///
+    /// A manual skip will just play this track sooner, since it's still
+    /// the front of the buffer, but anything which changes what should be
+    /// played next has to clear [Player::prefetched] & [Player::queued] itself.
+    ///
+    /// This gets aborted by skips, so the track is only taken out of the buffer once it's
+    /// been decoded, since otherwise it could be lost partway through.
+    async fn prefetch(player: Arc<Self>, itx: Sender<()>, delay: Duration) {
+        tokio::time::sleep(delay).await;
+
+        // While repeating, the next track might've been prefetched during an earlier loop.
+        if player.prefetched.lock().await.is_none() {
+            let front = player.tracks.read().await.front().cloned();
+            let (track, buffered) = match front {
+                Some(track) => (track, true),
+                None => match player.pop(Arc::default()).await {
+                    Ok(track) => (track, false),
+                    Err(_) => return,
+                },
+            };
+
+            let decoded = match player.decode(&track).await {
+                Ok(decoded) => decoded,
+                Err(error) => {
+                    // The track is just skipped, and is replaced once the next one is needed.
+                    if buffered {
+                        player.tracks.write().await.retain(|x| x.id != track.id);
+                    }
+
+                    player.log_error(&error).await;
+                    return;
+                }
+            };
+
+            // There's nothing to wait on between taking the track & storing it, so it can't be lost.
+            let mut prefetched = player.prefetched.lock().await;
+            let mut tracks = player.tracks.write().await;
+            if buffered {
+                match tracks.front() {
+                    Some(x) if x.id == track.id => {
+                        tracks.pop_front();
+                    }
+                    // The buffer was changed while decoding, like by toggling shuffle.
+                    _ => return,
+                }
+            }
+
+            drop(tracks);
+            *prefetched = Some((track, decoded));
+            drop(prefetched);
+
+            // The track came out of the buffer, so it has a free spot now.
+            let _ = Downloader::notify(&itx).await;
+        }
+
+        let _ = player.queue_next().await;
+    }
+
+    /// Starts prefetching the next track partway through the current one, which lasts `duration`.
+    fn schedule_prefetch(player: &Arc<Self>, itx: &Sender<()>, duration: Option<Duration>) {
+        if player.once {
+            return;
+        }
+
+        let delay = duration.map_or(PREFETCH_FALLBACK, |x| x.mul_f32(PREFETCH_POINT));
+
+        let prefetcher = task::spawn(Self::prefetch(player.clone(), itx.clone(), delay));
+        if let Some(previous) = player.prefetcher.lock().unwrap().replace(prefetcher) {
+            previous.abort();
+        }
+    }
+
+    /// Appends `source` to the sink as the current track's source, which has `underruns` if it's streamed.
+    fn append<S>(&self, source: S, underruns: Option<Underruns>)
+    where
+        S: Source + Send + 'static,
+        S::Item: Sample + Send,
+        f32: rodio::cpal::FromSample<S::Item>,
+    {
+        let id = self.signals.id();
+        self.source.store(id, Ordering::Release);
+        *self.underruns.lock().unwrap() = underruns;
+        self.push(source, id, State::default());
+    }
+
+    /// Wraps `source` in a [Tracked] source, and then appends it to the sink.
+    fn push<S>(&self, source: S, id: u64, state: State)
+    where
+        S: Source + Send + 'static,
+        S::Item: Sample + Send,
+        f32: rodio::cpal::FromSample<S::Item>,
+    {
+        let tracked = Tracked::new(source, id, Arc::clone(&self.signals), state, self.gap);
+
+        #[cfg(feature = "visualizer")]
+        if let Some(visualizer) = &self.visualizer {
+            self.sink
+                .append(visualizer::Tap::new(tracked, Arc::clone(visualizer)));
+            return;
+        }
+
+        self.sink.append(tracked);
+    }
+
+    /// Appends whatever should play after the current track to the sink, so that there's no gap.
+    ///
+    /// This is the current track again while repeating, and otherwise it's the prefetched track.
+    /// Nothing is queued up while crossfading, since the next track has to fade in instead.
+    async fn queue_next(&self) -> eyre::Result<()> {
+        if !self.crossfade.is_zero()
+            || !self.current_exists()
+            || self.queued.lock().unwrap().is_some()
+        {
+            return Ok(());
+        }
+
+        let (track, decoded, replay) = if self.repeat.load(Ordering::Relaxed) {
+            let Some(track) = self.playing.load_full() else {
+                return Ok(());
+            };
+
+            let track = (*track).clone();
+            let mut decoded = track.clone().decode()?;
+            decoded.info.gain = self.gain();
+            (track, decoded, true)
+        } else {
+            let Some((track, decoded)) = self.prefetched.lock().await.take() else {
+                return Ok(());
+            };
+
+            (track, decoded, false)
+        };
+
+        let id = self.signals.id();
+        let state = State::default();
+        let underruns = decoded.data.underruns();
+        let data = decoded.data.amplify(decoded.info.gain);
+
+        // This has to be set before appending, in case the current track ends right away.
+        *self.queued.lock().unwrap() = Some(Queued {
+            id,
+            track,
+            info: decoded.info,
+            underruns,
+            replay,
+            state: state.clone(),
+        });
+
+        self.push(data, id, state);
+
+        Ok(())
+    }
+
+    /// Takes the queued track out of the sink if it matches `filter`, which only
+    /// works if it hasn't started playing yet. Otherwise, it's left to [Messages::Advance].
+    fn unqueue(&self, filter: impl FnOnce(&Queued) -> bool) -> Option<Queued> {
+        let mut queued = self.queued.lock().unwrap();
+        if !queued
+            .as_ref()
+            .is_some_and(|x| filter(x) && x.state.cancel())
+        {
+            return None;
+        }
+
+        // The play loop might've been waiting for this track to start.
+        self.signals.changed.notify_one();
+
+        queued.take()
+    }
+
+    /// Replaces the queued track, since what should play next has changed.
+    async fn requeue(&self) -> eyre::Result<()> {
+        if let Some(queued) = self.unqueue(|_| true) {
+            if !queued.replay {
+                *self.prefetched.lock().await = Some(queued.restore()?);
+            }
+        }
+
+        self.queue_next().await
+    }
+
+    /// Works out what should happen after one of the sources in the sink has started or finished.
+    fn transition(&self) -> Option<Messages> {
+        let queued = self.queued.lock().unwrap();
+        if let Some(queued) = queued.as_ref() {
+            return (queued.id == self.signals.playing()).then_some(Messages::Advance);
+        }
+
+        if self.signals.finished() != self.source.load(Ordering::Acquire) {
+            return None;
+        }
+
+        Some(if self.repeat.load(Ordering::Relaxed) {
+            Messages::Replay
+        } else {
+            Messages::Next
+        })
+    }
+
+    /// Lets everything else know that `info` has just started playing.
+    ///
+    /// `notify` is whether a desktop notification should be sent & the `--on-track`
+    /// command should be ran, which isn't the case for repeats.
+    fn announce(player: &Arc<Self>, info: &TrackInfo, notify: bool) {
+        // A blank name is usually a sign of a messy list, so it's worth pointing out which track it was.
+        if notify && info.name == list::UNTITLED {
+            let clone = Arc::clone(player);
+            let message = format!("{} doesn't have a name", info.id);
+            task::spawn(async move { clone.log(&message).await });
+        }
+
+        // The command could take a while, or fail, neither of which should affect playback.
+        if let Some(command) = player.on_track.clone().filter(|_| notify) {
+            let clone = Arc::clone(player);
+            let name = info.name.clone();
+            task::spawn(async move {
+                if let Err(error) = hook::run(&command, &name).await {
+                    clone.log_error(&error).await;
+                }
+            });
+        }
+
+        #[cfg(any(feature = "scrobble", feature = "listenbrainz"))]
+        if let Some(scrobbler) = &player.scrobbler {
+            let scrobbler = Arc::clone(scrobbler);
+            let info = info.clone();
+            task::spawn(async move {
+                scrobbler.flush().await;
+                scrobbler.start(&info).await;
+            });
+        }
+
+        #[cfg(feature = "discord")]
+        Self::update_presence(player);
+
+        // Notifications are skipped while paused, since they'd just be noise.
+        #[cfg(feature = "notify")]
+        if notify && player.notifier.is_some() && !player.sink.is_paused() {
+            let clone = Arc::clone(player);
+            let name = info.name.clone();
+            task::spawn_blocking(move || {
+                if let Some(notifier) = &clone.notifier {
+                    notifier.send(&name);
+                }
+            });
+        }
+    }
+
+    /// Moves on to `queued`, which has just started playing after the current track ended.
+    ///
+    /// Unlike [Player::handle_next], this doesn't touch the sink at all, since the track is already in it.
+    async fn handle_advance(
+        player: Arc<Self>,
+        itx: Sender<()>,
+        queued: Queued,
+    ) -> eyre::Result<()> {
+        if let Some(previous) = player.current.load_full() {
+            // The previous track played right to the end, although it might've been seeked through.
+            let played = player.played();
+
+            #[cfg(any(feature = "scrobble", feature = "listenbrainz"))]
+            if let Some(scrobbler) = &player.scrobbler {
+                scrobbler.finish(played);
+            }
+
+            player.record(previous, played);
+        }
+
+        if !queued.replay {
+            player.remember();
+            player.playing.store(Some(Arc::new(queued.track)));
+        }
+
+        let duration = queued.info.duration;
+        player.set_current(queued.info.clone()).await?;
+        Self::announce(&player, &queued.info, !queued.replay);
+
+        Self::schedule_prefetch(&player, &itx, duration);
+        player.queue_next().await
     }
 
     /// This basically just calls [`Player::next`], and then appends the new track to the player.
@@ -201,28 +1538,103 @@ impl Player {
     /// if it fails. This functions purpose is to be called in the background, so that
     /// when the audio server recieves a `Next` signal it will still be able to respond to other
     /// signals while it's loading.
+    ///
+    /// `fade` is whether this was started by a crossfade, in which case the
+    /// previous track is still fading out on [Player::fader], and the new one will fade in.
+    /// `rewind` is the track to go back to, if this was started by [Messages::Previous].
     async fn handle_next(
         player: Arc<Self>,
         itx: Sender<()>,
         tx: Sender<Messages>,
+        fade: bool,
+        rewind: Option<Track>,
     ) -> eyre::Result<()> {
+        let played = player.played();
+
+        // Whatever was playing before has now finished, so it might need to be scrobbled.
+        #[cfg(any(feature = "scrobble", feature = "listenbrainz"))]
+        if let Some(scrobbler) = &player.scrobbler {
+            scrobbler.finish(played);
+        }
+
+        // Whatever was going to be prefetched is about to be played right away instead.
+        if let Some(prefetcher) = player.prefetcher.lock().unwrap().take() {
+            prefetcher.abort();
+        }
+
+        // A manual skip should cancel any crossfade, including one that's already started.
+        if let Some(crossfader) = player.crossfader.lock().unwrap().take() {
+            crossfader.abort();
+        }
+
+        if !fade {
+            player.fader.stop();
+        }
+
+        if let Some(previous) = player.current.load_full() {
+            player.record(previous, played);
+
+            if rewind.is_none() {
+                player.remember();
+            }
+        }
+
         // Serves as an indicator that the queue is "loading".
+        player.loading.store(true, Ordering::Relaxed);
         player.current.store(None);
+        player.redraw.notify_one();
 
-        // Stop the sink.
+        #[cfg(feature = "mpris")]
+        player.playback_changed().await;
+
+        // Stop the sink, which also gets rid of whatever was queued up after the current track.
         player.sink.stop();
 
-        let track = player.next().await;
+        let track = match rewind {
+            Some(track) => player.rewind(track).await,
+            None => player.next().await,
+        };
 
         match track {
             Ok(track) => {
+                player.failures.store(0, Ordering::Relaxed);
+
                 // Set the current track.
                 player.set_current(track.info.clone()).await?;
+                Self::announce(&player, &track.info, true);
 
                 // Actually start playing it, this is done later so that the amount
                 // or times where "loading" appears briefly even though the track is
                 // from the buffer is minimized.
-                player.sink.append(track.data);
+                let duration = track.info.duration;
+                let underruns = track.data.underruns();
+                let data = track.data.amplify(track.info.gain);
+                if fade {
+                    player.append(data.fade_in(player.crossfade), underruns);
+                } else if !player.started.swap(true, Ordering::Relaxed) {
+                    // The first track fades in, so that lowfi doesn't start out at full volume.
+                    player.append(data.fade_in(player.fade), underruns);
+                } else {
+                    player.append(data, underruns);
+                }
+
+                player.loading.store(false, Ordering::Relaxed);
+                player.redraw.notify_one();
+
+                // Crossfading needs to know when the track ends, so it's skipped if that's unknown.
+                if let Some(duration) = duration.filter(|x| {
+                    !player.once && !player.crossfade.is_zero() && *x > player.crossfade
+                }) {
+                    let crossfader =
+                        task::spawn(Self::crossfade(player.clone(), tx.clone(), duration));
+                    *player.crossfader.lock().unwrap() = Some(crossfader);
+                }
+
+                // Start getting the next track ready in the background.
+                Self::schedule_prefetch(&player, &itx, duration);
+
+                // While repeating, the current track can be queued up again straight away.
+                player.queue_next().await?;
 
                 // Notify the background downloader that there's an empty spot
                 // in the buffer.
@@ -231,8 +1643,21 @@ impl Player {
                 // Notify the audio server that the next song has actually been downloaded.
                 tx.send(Messages::NewSong).await?
             }
+            Err(error) if Self::is_undecodable(&error) => {
+                // The track is most likely just corrupt, so lowfi moves straight on to another.
+                player.log_error(&error).await;
+                player.failures.fetch_add(1, Ordering::Relaxed);
+
+                tx.send(Messages::TryAgain).await?
+            }
+            // This is `--at-end stop`, where the last track has just finished.
+            Err(_) if player.list.is_finished() => tx.send(Messages::Quit).await?,
             Err(error) => {
-                if !error.downcast::<reqwest::Error>()?.is_timeout() {
+                let timeout = error
+                    .downcast_ref::<reqwest::Error>()
+                    .is_some_and(|x| x.is_timeout());
+
+                if !timeout {
                     tokio::time::sleep(TIMEOUT).await;
                 }
 
@@ -243,6 +1668,388 @@ impl Player {
         Ok(())
     }
 
+    /// Bookmarks the current track, and then lets the user know whether it worked.
+    async fn handle_bookmark(player: Arc<Self>) {
+        let Some(current) = player.current.load_full() else {
+            return;
+        };
+
+        player.flash(match saved::add(saved::BOOKMARKS, &current.id).await {
+            Ok(true) => "bookmarked",
+            Ok(false) => "already bookmarked",
+            Err(_) => "couldn't bookmark",
+        });
+    }
+
+    /// Saves the current volume as the volume of the current track, which has to be bookmarked.
+    ///
+    /// The usual volume is still restored once the track is over.
+    async fn handle_remember_volume(player: Arc<Self>) {
+        let Some(current) = player.current.load_full() else {
+            return;
+        };
+
+        let bookmarked = saved::read(saved::BOOKMARKS)
+            .await
+            .is_ok_and(|x| x.contains(&current.id));
+        if !bookmarked {
+            player.flash("only bookmarks can have a volume");
+            return;
+        }
+
+        let volume = player.volume();
+        if saved::set_volume(&current.id, volume).await.is_err() {
+            player.flash("couldn't save the volume");
+            return;
+        }
+
+        player
+            .volumes
+            .write()
+            .unwrap()
+            .insert(current.id.clone(), volume);
+        player.overridden.store(true, Ordering::Relaxed);
+        player.flash("remembered the volume");
+    }
+
+    /// Blocks the current track, so that it's never played again, and then skips it.
+    async fn handle_block(player: Arc<Self>, tx: Sender<Messages>) -> eyre::Result<()> {
+        let Some(current) = player.current.load_full() else {
+            return Ok(());
+        };
+
+        if !player.list.block(&current.id) {
+            player.flash("can't block the last track");
+            return Ok(());
+        }
+
+        // The track is still blocked for this session even if it couldn't be saved.
+        if saved::add(saved::BLOCKLIST, &current.id).await.is_err() {
+            player.flash("couldn't save the blocklist");
+        }
+
+        // The track might also be waiting in the buffer, or already prefetched.
+        player.tracks.write().await.retain(|x| x.id != current.id);
+
+        let mut prefetched = player.prefetched.lock().await;
+        if prefetched.as_ref().is_some_and(|x| x.0.id == current.id) {
+            *prefetched = None;
+        }
+        drop(prefetched);
+
+        player.unqueue(|x| x.track.id == current.id);
+        tx.send(Messages::Next).await?;
+
+        Ok(())
+    }
+
+    /// Switches between shuffling & playing the list in order.
+    ///
+    /// When switching to sequential, the list will continue from the current track.
+    /// The buffer is also cleared, since it was filled in the old order.
+    async fn toggle_shuffle(&self) {
+        let shuffle = !self.list.is_shuffled();
+        if !shuffle {
+            if let Some(current) = self.current.load().as_ref() {
+                self.list.continue_from(&current.id);
+            }
+        }
+
+        self.list.set_shuffle(shuffle);
+        self.tracks.write().await.clear();
+        *self.prefetched.lock().await = None;
+
+        // Repeats don't depend on the order, so they can stay queued.
+        self.unqueue(|x| !x.replay);
+
+        #[cfg(feature = "mpris")]
+        if let Some(server) = self.mpris.get() {
+            let _ = server
+                .properties_changed([mpris_server::Property::Shuffle(shuffle)])
+                .await;
+        }
+    }
+
+    /// Waits until the current track is almost over, and then starts fading out its
+    /// final few seconds on [Player::fader] while the next track fades in on the sink.
+    ///
+    /// `duration` is the duration of the current track.
+    async fn crossfade(
+        player: Arc<Self>,
+        tx: Sender<Messages>,
+        duration: Duration,
+    ) -> eyre::Result<()> {
+        let start = duration - player.crossfade;
+        loop {
+            let position = player.elapsed();
+            if position >= start {
+                break;
+            }
+
+            // This is capped, so that pausing & seeking are taken into account.
+            tokio::time::sleep((start - position).min(Duration::from_secs(1))).await;
+        }
+
+        // Repeating is handled once the track is actually over.
+        if player.repeat.load(Ordering::Relaxed) {
+            return Ok(());
+        }
+
+        let Some(track) = player.playing.load_full() else {
+            return Ok(());
+        };
+
+        // The end of the track is decoded again, since the sink can't give it back.
+        let mut tail = (*track).clone().decode()?.data;
+        if tail.try_seek(player.elapsed()).is_err() {
+            return Ok(());
+        }
+
+        let mut tail = tail.amplify(player.gain()).take_duration(player.crossfade);
+        tail.set_filter_fadeout();
+
+        player.fader.set_volume(player.sink.volume());
+        player.fader.append(tail);
+        tx.send(Messages::Crossfade).await?;
+
+        Ok(())
+    }
+
+    /// Updates the Discord status in the background, which is cleared while paused.
+    #[cfg(feature = "discord")]
+    fn update_presence(player: &Arc<Self>) {
+        if player.presence.is_none() {
+            return;
+        }
+
+        let name = player
+            .current
+            .load()
+            .as_ref()
+            .filter(|_| !player.sink.is_paused())
+            .map(|x| x.name.clone());
+
+        let clone = Arc::clone(player);
+        task::spawn_blocking(move || {
+            if let Some(presence) = &clone.presence {
+                presence.update(name.as_deref());
+            }
+        });
+    }
+
+    /// Adds `info` to the history in the background, since it's just finished playing
+    /// after `played` of it was listened to.
+    ///
+    /// Errors are ignored.
+    fn record(&self, info: Arc<TrackInfo>, played: Duration) {
+        let limit = self.history;
+        task::spawn(async move {
+            let _ = history::add(&info, played, limit).await;
+        });
+    }
+
+    /// Plays the current track again from the start, which is used when repeating
+    /// if the repeat wasn't already queued up.
+    ///
+    /// This doesn't touch the buffer at all, since nothing new is being played.
+    async fn handle_replay(player: Arc<Self>, tx: Sender<Messages>) -> eyre::Result<()> {
+        let Some(track) = player.playing.load_full() else {
+            // There's nothing to repeat, so just move on to a new track instead.
+            tx.send(Messages::TryAgain).await?;
+            return Ok(());
+        };
+
+        let played = player.played();
+        if let Some(previous) = player.current.load_full() {
+            player.record(previous, played);
+        }
+
+        let mut track = (*track).clone().decode()?;
+        track.info.gain = player.gain();
+        player.reset_played();
+
+        // A repeat still counts as a separate listen.
+        #[cfg(any(feature = "scrobble", feature = "listenbrainz"))]
+        if let Some(scrobbler) = &player.scrobbler {
+            scrobbler.finish(played);
+
+            let scrobbler = Arc::clone(scrobbler);
+            let info = track.info.clone();
+            task::spawn(async move {
+                scrobbler.flush().await;
+                scrobbler.start(&info).await;
+            });
+        }
+
+        let underruns = track.data.underruns();
+        player.append(track.data.amplify(track.info.gain), underruns);
+        tx.send(Messages::NewSong).await?;
+
+        Ok(())
+    }
+
+    /// Goes back to the start of the current track.
+    ///
+    /// This is just a seek, unless the track doesn't support seeking,
+    /// in which case it's decoded again & replaces what's in the sink.
+    async fn handle_restart(player: Arc<Self>) -> eyre::Result<()> {
+        if !player.current_exists() {
+            return Ok(());
+        }
+
+        if player.seekable.load(Ordering::Relaxed) {
+            Self::handle_seek(player.clone(), Duration::ZERO).await?;
+            if player.seekable.load(Ordering::Relaxed) {
+                return Ok(());
+            }
+        }
+
+        let Some(track) = player.playing.load_full() else {
+            return Ok(());
+        };
+
+        let mut decoded = (*track).clone().decode()?;
+        decoded.info.gain = player.gain();
+
+        // Stopping the sink also gets rid of the queued track, which has to be put back afterwards.
+        player.sink.stop();
+        let queued = player.queued.lock().unwrap().take();
+        if let Some(queued) = queued.filter(|x| !x.replay) {
+            *player.prefetched.lock().await = Some(queued.restore()?);
+        }
+
+        let underruns = decoded.data.underruns();
+        player.append(decoded.data.amplify(decoded.info.gain), underruns);
+        player.redraw.notify_one();
+
+        player.queue_next().await
+    }
+
+    /// Gets the default output device & the names of all the output devices.
+    fn devices(&self) -> eyre::Result<(Option<String>, Vec<String>)> {
+        let get = || Ok((devices::default_name(), devices::names()?));
+
+        if self.silent {
+            Self::silently(get)
+        } else {
+            get()
+        }
+    }
+
+    /// Watches the output devices, and pauses if the default one changes or a device disappears,
+    /// which is usually what happens when headphones are unplugged.
+    ///
+    /// Resuming is left up to the user, so that it's never a surprise.
+    async fn watch_devices(player: Arc<Self>, tx: Sender<Messages>) -> eyre::Result<()> {
+        let clone = Arc::clone(&player);
+        let mut last = task::spawn_blocking(move || clone.devices()).await??;
+
+        loop {
+            tokio::time::sleep(DEVICE_POLL).await;
+
+            let clone = Arc::clone(&player);
+            let Ok(current) = task::spawn_blocking(move || clone.devices()).await? else {
+                continue;
+            };
+
+            let unplugged = current.0 != last.0 || last.1.iter().any(|x| !current.1.contains(x));
+            if unplugged && !player.sink.is_paused() {
+                tx.send(Messages::Pause).await?;
+            }
+
+            last = current;
+        }
+    }
+
+    /// Watches the position of the sink, and pauses if it stops moving while it should be playing.
+    ///
+    /// [rodio] doesn't say when the output stream has died, which sometimes happens after the
+    /// system suspends, so this is the only way to tell. The stream can't be rebuilt either, since
+    /// the sink belongs to it, so the user is asked to resume instead. If it keeps on stalling
+    /// after being resumed [MAX_STALLS] times in a row, then this gives up.
+    async fn watch_stream(player: Arc<Self>, tx: Sender<Messages>) -> eyre::Result<()> {
+        let mut last = (0, Duration::ZERO);
+        let mut stalls = 0;
+
+        while stalls < MAX_STALLS {
+            tokio::time::sleep(STALL_POLL).await;
+
+            // A streamed track plays silence while waiting for the download, so it still moves.
+            let playing = !player.sink.is_paused()
+                && !player.sink.empty()
+                && player.current_exists()
+                && !player.loading.load(Ordering::Relaxed);
+
+            let current = (player.source.load(Ordering::Acquire), player.sink.get_pos());
+            let stalled = playing && current == last;
+            last = current;
+
+            if !stalled {
+                // Only playing properly for a while means that the stream has recovered.
+                if playing {
+                    stalls = 0;
+                }
+
+                continue;
+            }
+
+            stalls += 1;
+            player.stalled.store(true, Ordering::Relaxed);
+            tx.send(Messages::Pause).await?;
+        }
+
+        Ok(())
+    }
+
+    /// Gets the position which is `seconds` away from the current position in the track.
+    fn relative_position(&self, seconds: i64) -> Duration {
+        let position = self.elapsed();
+        let offset = Duration::from_secs(seconds.unsigned_abs());
+
+        if seconds < 0 {
+            position.saturating_sub(offset)
+        } else {
+            position + offset
+        }
+    }
+
+    /// Seeks to `target` in the current track.
+    ///
+    /// This is also meant to be called in the background, since [Sink::try_seek]
+    /// blocks until the seek is actually done. If the track doesn't support
+    /// seeking, then this will just do nothing.
+    async fn handle_seek(player: Arc<Self>, target: Duration) -> eyre::Result<()> {
+        // There's nothing to seek in if the track is still loading.
+        if !player.current_exists() {
+            return Ok(());
+        }
+
+        let clone = Arc::clone(&player);
+        if let Err(error) = task::spawn_blocking(move || clone.sink.try_seek(target)).await? {
+            if let SeekError::NotSupported { .. } = error {
+                player.seekable.store(false, Ordering::Relaxed);
+            }
+
+            return Ok(());
+        }
+
+        // Wait for the sink to actually update the position before redrawing.
+        tokio::time::sleep(SINK_UPDATE).await;
+        player.redraw.notify_one();
+
+        #[cfg(feature = "mpris")]
+        if let Some(server) = player.mpris.get() {
+            let position = player.elapsed().as_micros() as i64;
+            server
+                .emit(mpris_server::Signal::Seeked {
+                    position: mpris_server::Time::from_micros(position),
+                })
+                .await?;
+        }
+
+        Ok(())
+    }
+
     /// This is the main "audio server".
     ///
     /// `rx` & `tx` are used to communicate with it, for example when to
@@ -260,8 +2067,16 @@ impl Player {
         // Start buffering tracks immediately.
         Downloader::notify(&itx).await?;
 
+        let watcher = player
+            .pause_on_unplug
+            .then(|| task::spawn(Self::watch_devices(player.clone(), tx.clone())));
+        let stream = task::spawn(Self::watch_stream(player.clone(), tx.clone()));
+
         // Set the initial sink volume to the one specified.
         player.set_volume(properties.volume as f32 / 100.0);
+        player
+            .minimalist
+            .store(properties.minimalist, Ordering::Relaxed);
 
         // Whether the last signal was a `NewSong`.
         // This is helpful, since we only want to autoplay
@@ -269,25 +2084,21 @@ impl Player {
         let mut new = false;
 
         loop {
-            let clone = Arc::clone(&player);
-
             let msg = select! {
                 biased;
 
                 Some(x) = rx.recv() => x,
-                // This future will finish only at the end of the current track.
-                // The condition is a kind-of hack which gets around the quirks
-                // of `sleep_until_end`.
-                //
-                // That's because `sleep_until_end` will return instantly if the sink
-                // is uninitialized. That's why we put a check to make sure that the last
-                // signal we got was `NewSong`, since we shouldn't start waiting for the
-                // song to be over until it has actually started.
+                // This finishes whenever a source in the sink starts or ends, which is
+                // how lowfi knows when the current track is over. There's a check to make
+                // sure that the last signal we got was `NewSong`, since we shouldn't start
+                // waiting for the song to be over until it has actually started.
                 //
                 // It's also important to note that the condition is only checked at the
                 // beginning of the loop, not throughout.
-                Ok(_) = task::spawn_blocking(move || clone.sink.sleep_until_end()),
-                        if new => Messages::Next,
+                () = player.signals.changed.notified(), if new => match player.transition() {
+                    Some(x) => x,
+                    None => continue,
+                },
             };
 
             match msg {
@@ -296,24 +2107,163 @@ impl Player {
                     // to be over until we recieve the `NewSong` signal.
                     new = false;
 
+                    // Lots of tracks failing to decode in a row means that something else is wrong.
+                    if player.failures.load(Ordering::Relaxed) >= MAX_FAILURES {
+                        break;
+                    }
+
                     // This basically just prevents `Next` while a song is still currently loading.
                     if msg == Messages::Next && !player.current_exists() {
                         continue;
                     }
 
+                    // With `--once`, the first track being over or skipped is the end.
+                    if player.once && msg == Messages::Next {
+                        break;
+                    }
+
                     // Handle the rest of the signal in the background,
                     // as to not block the main audio thread.
-                    task::spawn(Self::handle_next(player.clone(), itx.clone(), tx.clone()));
+                    task::spawn(Self::handle_next(
+                        player.clone(),
+                        itx.clone(),
+                        tx.clone(),
+                        false,
+                        None,
+                    ));
                 }
-                Messages::PlayPause => {
-                    if player.sink.is_paused() {
+                Messages::Previous => {
+                    if !player.current_exists() {
+                        continue;
+                    }
+
+                    let previous = if player.elapsed() < PREVIOUS_THRESHOLD {
+                        player.previous.lock().unwrap().pop_back()
+                    } else {
+                        None
+                    };
+
+                    // Without anything to go back to, this is the same as restarting.
+                    let Some(previous) = previous else {
+                        task::spawn(Self::handle_restart(player.clone()));
+                        continue;
+                    };
+
+                    new = false;
+                    task::spawn(Self::handle_next(
+                        player.clone(),
+                        itx.clone(),
+                        tx.clone(),
+                        false,
+                        Some(previous),
+                    ));
+                }
+                Messages::PlayPause | Messages::Play | Messages::Pause => {
+                    let paused = player.sink.is_paused();
+                    let pause = match msg {
+                        Messages::Play => false,
+                        Messages::Pause => true,
+                        _ => !paused,
+                    };
+
+                    if pause == paused {
+                        continue;
+                    }
+
+                    if paused {
                         player.sink.play();
+                        player.fader.play();
+                        player.stalled.store(false, Ordering::Relaxed);
                     } else {
                         player.sink.pause();
+                        player.fader.pause();
                     }
+
+                    player.update_listened();
+
+                    #[cfg(feature = "discord")]
+                    Self::update_presence(&player);
+
+                    // Media keys usually go to whichever player last said it was playing.
+                    #[cfg(feature = "mpris")]
+                    player.playback_changed().await;
                 }
                 Messages::ChangeVolume(change) => {
-                    player.set_volume(player.sink.volume() + change);
+                    Self::ramp_volume(&player, player.volume() + change);
+                    ui::volume_changed();
+                }
+                Messages::SetVolume(volume) => {
+                    Self::ramp_volume(&player, volume);
+                    ui::volume_changed();
+                }
+                Messages::ToggleMute => {
+                    player.toggle_mute();
+                    ui::volume_changed();
+                }
+                Messages::ToggleRepeat => {
+                    player.repeat.fetch_xor(true, Ordering::Relaxed);
+
+                    let clone = Arc::clone(&player);
+                    task::spawn(async move { clone.requeue().await });
+                }
+                Messages::ToggleHelp => {
+                    player.help.fetch_xor(true, Ordering::Relaxed);
+                }
+                Messages::ToggleControls => {
+                    player.minimalist.fetch_xor(true, Ordering::Relaxed);
+                }
+                Messages::Bookmark => {
+                    task::spawn(Self::handle_bookmark(player.clone()));
+                }
+                Messages::RememberVolume => {
+                    task::spawn(Self::handle_remember_volume(player.clone()));
+                }
+                Messages::Block => {
+                    task::spawn(Self::handle_block(player.clone(), tx.clone()));
+                }
+                Messages::ToggleShuffle => {
+                    player.toggle_shuffle().await;
+
+                    // Refill the buffer in the new order.
+                    Downloader::notify(&itx).await?;
+                }
+                Messages::Crossfade => {
+                    new = false;
+                    task::spawn(Self::handle_next(
+                        player.clone(),
+                        itx.clone(),
+                        tx.clone(),
+                        true,
+                        None,
+                    ));
+                }
+                Messages::Replay => {
+                    new = false;
+                    task::spawn(Self::handle_replay(player.clone(), tx.clone()));
+                }
+                Messages::Advance => {
+                    // This is taken right away, so that the same track can't be advanced to twice.
+                    let mut queued = player.queued.lock().unwrap();
+                    if queued.as_ref().map(|x| x.id) != Some(player.signals.playing()) {
+                        continue;
+                    }
+
+                    if let Some(queued) = queued.take() {
+                        player.source.store(queued.id, Ordering::Release);
+                        *player.underruns.lock().unwrap() = queued.underruns.clone();
+                        task::spawn(Self::handle_advance(player.clone(), itx.clone(), queued));
+                    }
+                }
+                Messages::Restart => {
+                    task::spawn(Self::handle_restart(player.clone()));
+                }
+                Messages::Seek(seconds) => {
+                    let target = player.relative_position(seconds);
+                    task::spawn(Self::handle_seek(player.clone(), target));
+                }
+                #[cfg(feature = "mpris")]
+                Messages::SetPosition(position) => {
+                    task::spawn(Self::handle_seek(player.clone(), position));
                 }
                 // This basically just continues, but more importantly, it'll re-evaluate
                 // the select macro at the beginning of the loop.
@@ -327,10 +2277,72 @@ impl Player {
                 }
                 Messages::Quit => break,
             }
+
+            // Pretty much every message changes something that's displayed.
+            player.redraw.notify_one();
         }
 
         downloader.abort();
+        player.fade_out(player.fade).await;
+
+        if let Some(watcher) = watcher {
+            watcher.abort();
+        }
+        stream.abort();
+
+        #[cfg(any(feature = "scrobble", feature = "listenbrainz"))]
+        if let Some(scrobbler) = &player.scrobbler {
+            scrobbler.quit(player.played()).await;
+        }
+
+        if let Some(current) = player.current.load_full() {
+            let _ = history::add(&current, player.played(), player.history).await;
+        }
+
+        #[cfg(feature = "discord")]
+        if player.presence.is_some() {
+            let clone = Arc::clone(&player);
+            let _ = task::spawn_blocking(move || {
+                if let Some(presence) = &clone.presence {
+                    presence.close();
+                }
+            })
+            .await;
+        }
+
+        ensure!(
+            player.failures.load(Ordering::Relaxed) < MAX_FAILURES,
+            "couldn't decode {MAX_FAILURES} tracks in a row, see `--log` for more details"
+        );
 
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use bytes::Bytes;
+
+    use super::Player;
+    use crate::tracks::{Data, Track};
+
+    #[test]
+    fn garbage_is_skipped() {
+        let track = Track {
+            id: String::from("https://example.com/garbage.mp3"),
+            name: String::from("Garbage"),
+            index: 0,
+            data: Data::Full(Bytes::from_static(b"this isn't an mp3 at all")),
+        };
+
+        let error = Player::decode_only(&track).err().unwrap();
+        assert!(Player::is_undecodable(&error));
+        assert!(error.to_string().contains(&track.id));
+    }
+
+    #[test]
+    fn other_errors_are_retried() {
+        let error = eyre::eyre!("couldn't download the track");
+        assert!(!Player::is_undecodable(&error));
+    }
+}
@@ -0,0 +1,53 @@
+//! Responsible for finding audio output devices, for `--device` & `--list-devices`.
+
+use eyre::bail;
+use rodio::{
+    cpal::{
+        self,
+        traits::{DeviceTrait, HostTrait},
+    },
+    Device,
+};
+
+/// Gets all of the output devices, alongside their names.
+fn all() -> eyre::Result<Vec<(String, Device)>> {
+    Ok(cpal::default_host()
+        .output_devices()?
+        .filter_map(|x| Some((x.name().ok()?, x)))
+        .collect())
+}
+
+/// Gets the names of all of the output devices.
+pub fn names() -> eyre::Result<Vec<String>> {
+    Ok(all()?.into_iter().map(|x| x.0).collect())
+}
+
+/// Gets the name of the default output device, if there is one.
+pub fn default_name() -> Option<String> {
+    cpal::default_host().default_output_device()?.name().ok()
+}
+
+/// Finds the first output device which has `name` in its name, ignoring case.
+///
+/// If there isn't one, then the error will list all of the devices which are available.
+pub fn find(name: &str) -> eyre::Result<Device> {
+    let devices = all()?;
+    let lowercase = name.to_lowercase();
+
+    if let Some(index) = devices
+        .iter()
+        .position(|x| x.0.to_lowercase().contains(&lowercase))
+    {
+        return Ok(devices.into_iter().nth(index).unwrap().1);
+    }
+
+    if devices.is_empty() {
+        bail!("couldn't find an output device matching `{name}`, since there aren't any");
+    }
+
+    let names: Vec<&str> = devices.iter().map(|x| x.0.as_str()).collect();
+    bail!(
+        "couldn't find an output device matching `{name}`, the available devices are:\n{}",
+        names.join("\n")
+    )
+}
@@ -0,0 +1,92 @@
+//! Contains the [Presence], which shows the current track on Discord.
+
+use std::{path::Path, sync::Mutex};
+
+use discord_rich_presence::{
+    activity::{Activity, ActivityType},
+    DiscordIpc, DiscordIpcClient,
+};
+use eyre::eyre;
+use tokio::fs;
+
+/// Shows the current track as a "Listening to" status on Discord.
+///
+/// This is configured with the `client_id` of a Discord application in `discord.toml`,
+/// and the name of that application is what shows up after "Listening to".
+/// Discord doesn't have to be running, since lowfi will just try again on the next update.
+pub struct Presence {
+    client_id: String,
+
+    /// The connection to Discord, which is [None] if it hasn't been made yet or was lost.
+    client: Mutex<Option<DiscordIpcClient>>,
+}
+
+impl Presence {
+    /// Loads the presence from `discord.toml` in the config directory.
+    ///
+    /// This is [None] if the file doesn't exist, since it has to be set up first.
+    pub async fn load(config: &Path) -> eyre::Result<Option<Self>> {
+        let path = config.join("discord.toml");
+        if !path.exists() {
+            return Ok(None);
+        }
+
+        let table: toml::Table = fs::read_to_string(path)
+            .await?
+            .parse()
+            .map_err(|error| eyre!("discord.toml is invalid: {error}"))?;
+
+        let client_id = table
+            .get("client_id")
+            .and_then(|x| x.as_str())
+            .ok_or(eyre!("`client_id` is missing from discord.toml"))?;
+
+        Ok(Some(Self {
+            client_id: client_id.to_owned(),
+            client: Mutex::new(None),
+        }))
+    }
+
+    /// Shows `name` as the current track, or clears the status if it's [None].
+    ///
+    /// This blocks, so it should be called with [tokio::task::spawn_blocking].
+    /// Any errors are ignored, and will just make lowfi reconnect next time.
+    pub fn update(&self, name: Option<&str>) {
+        let mut client = self.client.lock().unwrap();
+
+        if client.is_none() {
+            let mut new = DiscordIpcClient::new(&self.client_id);
+            if new.connect().is_err() {
+                return;
+            }
+
+            *client = Some(new);
+        }
+
+        let Some(connected) = client.as_mut() else {
+            return;
+        };
+
+        let result = match name {
+            Some(name) => connected.set_activity(
+                Activity::new()
+                    .activity_type(ActivityType::Listening)
+                    .details(name),
+            ),
+            None => connected.clear_activity(),
+        };
+
+        if result.is_err() {
+            let _ = connected.close();
+            *client = None;
+        }
+    }
+
+    /// Clears the status & disconnects from Discord, which is done when quitting.
+    pub fn close(&self) {
+        if let Some(mut client) = self.client.lock().unwrap().take() {
+            let _ = client.clear_activity();
+            let _ = client.close();
+        }
+    }
+}
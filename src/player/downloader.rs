@@ -1,15 +1,19 @@
 //! Contains the [`Downloader`] struct.
 
-use std::sync::Arc;
+use std::{sync::Arc, time::Duration};
 
 use tokio::{
     sync::mpsc::{self, Receiver, Sender},
     task::{self, JoinHandle},
 };
 
-use crate::tracks::Track;
+use super::Player;
 
-use super::{Player, BUFFER_SIZE};
+/// How long to wait after a track fails to download, which doubles for each failure in a row.
+const RETRY_DELAY: Duration = Duration::from_millis(500);
+
+/// The longest that [RETRY_DELAY] can end up doubling to.
+const MAX_RETRY_DELAY: Duration = Duration::from_secs(30);
 
 /// This struct is responsible for downloading tracks in the background.
 ///
@@ -48,14 +52,25 @@ impl Downloader {
         (
             self.tx,
             task::spawn(async move {
+                let mut delay = RETRY_DELAY;
+
                 // Loop through each update notification.
                 while self.rx.recv().await == Some(()) {
                     //  For each update notification, we'll push tracks until the buffer is completely full.
-                    while self.player.tracks.read().await.len() < BUFFER_SIZE {
-                        let Ok(track) = Track::random(&self.player.client).await else {
+                    while self.player.tracks.read().await.len() < self.player.buffer {
+                        let Ok(track) = self.player.fetch(false, Arc::default()).await else {
+                            // Nothing more can be fetched once the end of the list has been reached.
+                            if self.player.list.is_finished() {
+                                break;
+                            }
+
+                            // Local files fail right away, so this would spin without waiting.
+                            tokio::time::sleep(delay).await;
+                            delay = (delay * 2).min(MAX_RETRY_DELAY);
                             continue;
                         };
 
+                        delay = RETRY_DELAY;
                         self.player.tracks.write().await.push_back(track);
                     }
                 }
@@ -0,0 +1,277 @@
+//! Responsible for `history.tsv`, which has every track that's been listened to.
+//!
+//! Each line is a UNIX timestamp, the name of the track, its identifier & how many
+//! seconds of it were played, all seperated by tabs. Older lines don't have the last one.
+
+use std::{
+    collections::HashMap,
+    fs::File,
+    path::{Path, PathBuf},
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use tokio::{fs, io::AsyncWriteExt, task};
+
+use crate::{format::format_long_duration, paths, tracks::TrackInfo};
+
+/// The name of the history file, which is in the state directory.
+const FILE: &str = "history.tsv";
+
+/// How many tracks are shown by `--history`.
+const SHOWN: usize = 20;
+
+/// How long there has to be between two tracks for `lowfi stats` to count them as separate sessions.
+const SESSION_GAP: u64 = 30 * 60;
+
+/// Gets the path of the history file, which is usually `~/.local/state/lowfi/history.tsv`.
+///
+/// Platforms without a state directory will use the local data directory instead.
+pub async fn path() -> eyre::Result<PathBuf> {
+    let dir = paths::state()?;
+
+    if !dir.exists() {
+        fs::create_dir_all(&dir).await?;
+    }
+
+    Ok(dir.join(FILE))
+}
+
+/// An advisory lock on `history.tsv.lock`, which is released once it's dropped.
+///
+/// Appending takes a shared lock, and trimming takes an exclusive one, so that nothing
+/// is appended between the history being read & replaced by [trim]. This only does
+/// anything on unix, since that's the only place where [libc::flock()] is available.
+struct Lock {
+    _file: File,
+}
+
+impl Lock {
+    /// Waits until the lock is taken, which is `exclusive` when trimming.
+    async fn acquire(history: &Path, exclusive: bool) -> eyre::Result<Self> {
+        let path = history.with_extension("tsv.lock");
+
+        let file = task::spawn_blocking(move || -> std::io::Result<File> {
+            let file = File::options()
+                .create(true)
+                .truncate(false)
+                .write(true)
+                .open(path)?;
+
+            #[cfg(unix)]
+            {
+                use std::os::fd::AsRawFd;
+
+                let operation = if exclusive {
+                    libc::LOCK_EX
+                } else {
+                    libc::LOCK_SH
+                };
+
+                // SAFETY: The file descriptor is valid for as long as `file` is around.
+                if unsafe { libc::flock(file.as_raw_fd(), operation) } != 0 {
+                    return Err(std::io::Error::last_os_error());
+                }
+            }
+
+            #[cfg(not(unix))]
+            let _ = exclusive;
+
+            Ok(file)
+        })
+        .await??;
+
+        Ok(Self { _file: file })
+    }
+}
+
+/// Gets the current time, as a UNIX timestamp.
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs()
+}
+
+/// Adds `info` to the history, which is trimmed if it's grown larger than `limit` bytes.
+/// `played` is how much of the track was listened to, which is used by `lowfi stats`.
+///
+/// The whole line is written at once to a file opened in append mode,
+/// so multiple instances of lowfi won't mix up each other's lines.
+/// See [Lock] for how this avoids losing lines while the history is being trimmed.
+pub async fn add(info: &TrackInfo, played: Duration, limit: u64) -> eyre::Result<()> {
+    let path = path().await?;
+
+    // With `--gap`, the position can go a little past the end of the track.
+    let played = info.duration.map_or(played, |x| played.min(x));
+
+    // Tabs & newlines would break the format, although they shouldn't ever really show up.
+    let clean = |x: &str| x.replace(['\t', '\n', '\r'], " ");
+    let line = format!(
+        "{}\t{}\t{}\t{}\n",
+        now(),
+        clean(&info.name),
+        clean(&info.id),
+        played.as_secs()
+    );
+
+    let lock = Lock::acquire(&path, false).await?;
+    let mut file = fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(&path)
+        .await?;
+    file.write_all(line.as_bytes()).await?;
+
+    let length = file.metadata().await?.len();
+    drop(lock);
+
+    if length > limit {
+        trim(path, limit).await?;
+    }
+
+    Ok(())
+}
+
+/// Removes the oldest lines in the history, until it's only half of `limit` bytes.
+///
+/// This is written to a temporary file first, which is then renamed, so that
+/// the history is never left half written if lowfi quits partway through.
+/// The temporary file's name is unique to this instance, in case another is trimming too.
+async fn trim(path: PathBuf, limit: u64) -> eyre::Result<()> {
+    let _lock = Lock::acquire(&path, true).await?;
+    let contents = fs::read_to_string(&path).await?;
+
+    // Another instance might've trimmed the history while this one was waiting for the lock.
+    if contents.len() as u64 <= limit {
+        return Ok(());
+    }
+
+    let mut size = 0;
+    let mut kept: Vec<&str> = contents
+        .lines()
+        .rev()
+        .take_while(|x| {
+            size += x.len() as u64 + 1;
+            size <= limit / 2
+        })
+        .collect();
+    kept.reverse();
+
+    let temporary = path.with_extension(format!(
+        "tsv.{}-{:08x}.tmp",
+        std::process::id(),
+        rand::random::<u32>()
+    ));
+    fs::write(
+        &temporary,
+        kept.iter().map(|x| format!("{x}\n")).collect::<String>(),
+    )
+    .await?;
+    fs::rename(temporary, path).await?;
+
+    Ok(())
+}
+
+/// Formats how long ago `timestamp` was, like `5m ago`.
+fn ago(timestamp: u64) -> String {
+    let seconds = now().saturating_sub(timestamp);
+
+    match seconds {
+        0..=59 => format!("{seconds}s ago"),
+        60..=3599 => format!("{}m ago", seconds / 60),
+        3600..=86399 => format!("{}h ago", seconds / 3600),
+        _ => format!("{}d ago", seconds / 86400),
+    }
+}
+
+/// Prints the most recently played tracks, with the newest one last.
+pub async fn print() -> eyre::Result<()> {
+    let path = path().await?;
+    if !path.exists() {
+        println!("nothing has been played yet");
+        return Ok(());
+    }
+
+    let contents = fs::read_to_string(path).await?;
+    let lines: Vec<&str> = contents.lines().filter(|x| !x.is_empty()).collect();
+
+    for line in &lines[lines.len().saturating_sub(SHOWN)..] {
+        let mut fields = line.split('\t');
+        let (Some(timestamp), Some(name)) = (fields.next(), fields.next()) else {
+            continue;
+        };
+
+        let when = timestamp.parse().map_or_else(|_| String::from("?"), ago);
+        println!("{when:>8}  {name}");
+    }
+
+    Ok(())
+}
+
+/// Prints a summary of the whole history, which is how long was spent listening,
+/// how many sessions there were & the `top` most played tracks.
+///
+/// A new session starts whenever nothing was played for [SESSION_GAP].
+pub async fn stats(top: usize) -> eyre::Result<()> {
+    let path = path().await?;
+    if !path.exists() {
+        println!("no history yet");
+        return Ok(());
+    }
+
+    let contents = fs::read_to_string(path).await?;
+
+    let mut listened = 0;
+    let mut sessions = 0;
+    let mut last: Option<u64> = None;
+    let mut plays: HashMap<&str, (usize, &str)> = HashMap::new();
+
+    for line in contents.lines() {
+        let mut fields = line.split('\t');
+        let (Some(Ok(timestamp)), Some(name), Some(id)) = (
+            fields.next().map(str::parse::<u64>),
+            fields.next(),
+            fields.next(),
+        ) else {
+            continue;
+        };
+        let played = fields.next().and_then(|x| x.parse().ok()).unwrap_or(0);
+
+        // Each line is written once a track is over, so the track started `played` seconds earlier.
+        let started = timestamp.saturating_sub(played);
+        let new = match last {
+            Some(last) => started.saturating_sub(last) > SESSION_GAP,
+            None => true,
+        };
+        if new {
+            sessions += 1;
+        }
+        last = Some(timestamp);
+        listened += played;
+
+        // The name that's kept is the newest one, in case the way names are shown has changed.
+        let entry = plays.entry(id).or_insert((0, name));
+        *entry = (entry.0 + 1, name);
+    }
+
+    if sessions == 0 {
+        println!("no history yet");
+        return Ok(());
+    }
+
+    let listened = format_long_duration(&Duration::from_secs(listened));
+    let s = if sessions == 1 { "" } else { "s" };
+    println!("listened for {listened} over {sessions} session{s}");
+
+    let mut plays: Vec<(usize, &str)> = plays.into_values().collect();
+    plays.sort_by(|a, b| b.0.cmp(&a.0).then(a.1.cmp(b.1)));
+
+    if top > 0 {
+        println!("\nmost played:");
+        for (count, name) in plays.iter().take(top) {
+            println!("{count:>6}  {name}");
+        }
+    }
+
+    Ok(())
+}
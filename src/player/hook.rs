@@ -0,0 +1,110 @@
+//! Responsible for `--on-track`, which runs a command whenever a new track starts.
+
+use std::process::Stdio;
+
+use eyre::{ensure, WrapErr};
+use tokio::process::Command;
+
+/// The environment variable with the name of the track, which `%t` is replaced with a reference to.
+///
+/// The command is never given the name directly, so that a name with quotes in
+/// it can't change what the command does once the shell gets to it. That's only
+/// on unix though, since `cmd` expands variables before it parses anything, see `quote`.
+const VARIABLE: &str = "LOWFI_TRACK";
+
+/// Puts `name` in quotes for `cmd`, which is what `%t` is replaced with on Windows.
+///
+/// Inside of quotes, the only characters that `cmd` still cares about are quotes and
+/// percent signs, so those close the quotes, get escaped with `^`, and then reopen them.
+/// Line breaks would end the command, so they're replaced with spaces.
+#[cfg(any(windows, test))]
+fn quote(name: &str) -> String {
+    let mut quoted = String::from('"');
+    for character in name.chars() {
+        match character {
+            '"' | '%' => {
+                quoted.push_str("\"^");
+                quoted.push(character);
+                quoted.push('"');
+            }
+            '\r' | '\n' => quoted.push(' '),
+            _ => quoted.push(character),
+        }
+    }
+
+    quoted.push('"');
+    quoted
+}
+
+/// Runs `command` with the system's shell, where `%t` is the name of the track.
+///
+/// This waits for the command to finish, so it should be spawned as its own task.
+/// Nothing that the command prints is shown, since it would just mess up the UI.
+pub async fn run(command: &str, name: &str) -> eyre::Result<()> {
+    #[cfg(windows)]
+    let mut shell = {
+        // This is passed as is, since `cmd` doesn't follow the usual rules for quoting arguments.
+        let mut shell = Command::new("cmd");
+        shell.arg("/C").raw_arg(command.replace("%t", &quote(name)));
+        shell
+    };
+
+    #[cfg(not(windows))]
+    let mut shell = {
+        let mut shell = Command::new("sh");
+        shell
+            .arg("-c")
+            .arg(command.replace("%t", &format!("\"${VARIABLE}\"")));
+        shell
+    };
+
+    let status = shell
+        .env(VARIABLE, name)
+        .stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .status()
+        .await
+        .wrap_err("couldn't run the --on-track command")?;
+
+    ensure!(
+        status.success(),
+        "the --on-track command failed with {status}"
+    );
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::quote;
+
+    #[test]
+    fn quote_escapes_everything_cmd_would_expand() {
+        assert_eq!(quote("Snowman"), "\"Snowman\"");
+        assert_eq!(quote("a & b | c > d"), "\"a & b | c > d\"");
+        assert_eq!(quote("%PATH%"), "\"\"^%\"PATH\"^%\"\"");
+        assert_eq!(quote("say \"hi\""), "\"say \"^\"\"hi\"^\"\"\"");
+        assert_eq!(quote("one\r\ntwo"), "\"one  two\"");
+    }
+
+    #[test]
+    fn quote_keeps_metacharacters_inside_the_quotes() {
+        // Every character which `cmd` would treat as syntax has to be either quoted or escaped.
+        let quoted = quote("\" & calc & \"");
+
+        let mut inside = false;
+        let mut escaped = false;
+        for character in quoted.chars() {
+            match character {
+                _ if escaped => escaped = false,
+                '^' if !inside => escaped = true,
+                '"' => inside = !inside,
+                '&' => assert!(inside, "{quoted}"),
+                _ => (),
+            }
+        }
+
+        assert!(!inside, "{quoted}");
+    }
+}
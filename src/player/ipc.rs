@@ -0,0 +1,230 @@
+//! Contains the IPC socket, which is enabled with `--ipc` so that other
+//! programs, like status bars, can show what's playing & control lowfi.
+
+use std::{os::unix::fs::FileTypeExt, path::Path, sync::Arc, time::Duration};
+
+use eyre::{bail, eyre};
+use serde_json::json;
+use tokio::{
+    io::{AsyncBufReadExt, AsyncWriteExt, BufReader},
+    net::{UnixListener, UnixStream},
+    select,
+    sync::{mpsc::Sender, watch},
+    task,
+    time::sleep,
+};
+
+use super::{Messages, Player};
+
+/// How often the status is checked for changes.
+const POLL: Duration = Duration::from_millis(250);
+
+/// The parts of the status which are sent whenever they change.
+#[derive(Debug, Clone, PartialEq)]
+struct Status {
+    title: Option<String>,
+    volume: f32,
+    muted: bool,
+    paused: bool,
+}
+
+impl Status {
+    /// Gets the current status of `player`.
+    fn new(player: &Player) -> Self {
+        Self {
+            title: player.current.load().as_ref().map(|x| x.name.clone()),
+            volume: player.volume(),
+            muted: player.is_muted(),
+            paused: player.sink.is_paused(),
+        }
+    }
+
+    /// Formats the status as a single line of JSON,
+    /// which also includes the position in the current track.
+    fn line(&self, player: &Player) -> String {
+        let duration = player
+            .current
+            .load()
+            .as_ref()
+            .and_then(|x| x.duration)
+            .map(|x| x.as_secs_f64());
+
+        let line = json!({
+            "title": self.title,
+            "volume": (self.volume * 100.0).round() / 100.0,
+            "muted": self.muted,
+            "paused": self.paused,
+            "position": player.elapsed().as_secs_f64(),
+            "duration": duration,
+        });
+
+        format!("{line}\n")
+    }
+}
+
+/// Removes the socket at `path`, if it exists.
+///
+/// Anything at `path` which isn't a socket is left alone, in case `--ipc` was a typo.
+pub fn remove(path: &Path) {
+    let socket = std::fs::symlink_metadata(path).is_ok_and(|x| x.file_type().is_socket());
+    if socket {
+        let _ = std::fs::remove_file(path);
+    }
+}
+
+/// Parses a command sent by a client, like `next` or `volume +0.1`, into [Messages].
+///
+/// The volume can either be changed relatively with a sign, or set directly without one.
+fn parse(command: &str) -> eyre::Result<Messages> {
+    let (name, argument) = command
+        .trim()
+        .split_once(' ')
+        .map_or((command.trim(), None), |(x, y)| (x, Some(y.trim())));
+
+    let message = match (name, argument) {
+        ("next", None) => Messages::Next,
+        ("previous", None) => Messages::Previous,
+        ("pause", None) => Messages::PlayPause,
+        ("quit", None) => Messages::Quit,
+        ("mute", None) => Messages::ToggleMute,
+        ("bookmark", None) => Messages::Bookmark,
+        ("block", None) => Messages::Block,
+        ("repeat", None) => Messages::ToggleRepeat,
+        ("shuffle", None) => Messages::ToggleShuffle,
+        ("restart", None) => Messages::Restart,
+        ("volume", Some(volume)) => {
+            let parsed: f32 = volume
+                .parse()
+                .ok()
+                .filter(|x: &f32| x.is_finite())
+                .ok_or(eyre!("`{volume}` isn't a valid volume"))?;
+
+            if volume.starts_with(['+', '-']) {
+                Messages::ChangeVolume(parsed)
+            } else {
+                Messages::SetVolume(parsed)
+            }
+        }
+        ("seek", Some(seconds)) => Messages::Seek(
+            seconds
+                .parse()
+                .map_err(|_| eyre!("`{seconds}` isn't a valid amount of seconds"))?,
+        ),
+        ("volume" | "seek", None) => bail!("`{name}` needs an argument"),
+        _ => bail!("unknown command `{}`", command.trim()),
+    };
+
+    Ok(message)
+}
+
+/// Handles a single client, sending it the status right away and then whenever it changes.
+///
+/// The client can also send commands, one per line, which are passed on to `sender`.
+/// If a command is invalid, then the client is sent an error instead of being disconnected.
+/// This returns as soon as the client disconnects.
+async fn client(
+    player: Arc<Player>,
+    sender: Sender<Messages>,
+    stream: UnixStream,
+    mut status: watch::Receiver<Status>,
+) -> eyre::Result<()> {
+    let (reader, mut writer) = stream.into_split();
+    let mut lines = BufReader::new(reader).lines();
+
+    let line = status.borrow_and_update().line(&player);
+    writer.write_all(line.as_bytes()).await?;
+
+    loop {
+        select! {
+            changed = status.changed() => {
+                changed?;
+                let line = status.borrow_and_update().line(&player);
+                writer.write_all(line.as_bytes()).await?;
+            }
+            command = lines.next_line() => {
+                let Some(command) = command? else {
+                    return Ok(());
+                };
+
+                if command.trim().is_empty() {
+                    continue;
+                }
+
+                match parse(&command) {
+                    Ok(message) => sender.send(message).await?,
+                    Err(error) => {
+                        let line = json!({ "error": error.to_string() });
+                        writer.write_all(format!("{line}\n").as_bytes()).await?;
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// Creates the socket at `path`, which will replace an old socket, but nothing else.
+///
+/// This is done before anything else starts, so that a bad `--ipc` is an error instead of being ignored.
+pub fn bind(path: &Path) -> eyre::Result<UnixListener> {
+    // A previous instance of lowfi which didn't quit properly might've left its socket behind.
+    remove(path);
+    UnixListener::bind(path)
+        .map_err(|error| eyre!("couldn't create the socket at {}: {error}", path.display()))
+}
+
+/// Listens for clients on `listener`, which comes from [bind].
+///
+/// Every client gets sent a line of JSON whenever the track,
+/// the volume, or whether it's paused changes. See [client] for commands.
+pub async fn listen(
+    player: Arc<Player>,
+    sender: Sender<Messages>,
+    listener: UnixListener,
+) -> eyre::Result<()> {
+    let (tx, rx) = watch::channel(Status::new(&player));
+
+    loop {
+        select! {
+            accepted = listener.accept() => {
+                let (stream, _) = accepted?;
+                task::spawn(client(
+                    Arc::clone(&player),
+                    sender.clone(),
+                    stream,
+                    rx.clone(),
+                ));
+            }
+            _ = sleep(POLL) => {
+                tx.send_if_modified(|status| {
+                    let new = Status::new(&player);
+                    let changed = new != *status;
+                    *status = new;
+
+                    changed
+                });
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::bind;
+
+    #[tokio::test]
+    async fn only_sockets_are_replaced() {
+        let dir = std::env::temp_dir().join(format!("lowfi-ipc-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+
+        let file = dir.join("notes.txt");
+        std::fs::write(&file, "important").unwrap();
+        assert!(bind(&file).is_err());
+        assert_eq!(std::fs::read_to_string(&file).unwrap(), "important");
+
+        let socket = dir.join("lowfi.sock");
+        drop(bind(&socket).unwrap());
+        assert!(bind(&socket).is_ok());
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
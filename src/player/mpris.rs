@@ -1,4 +1,7 @@
-use std::sync::Arc;
+use std::{
+    sync::{atomic::Ordering, Arc},
+    time::Duration,
+};
 
 use mpris_server::{
     zbus::{fdo, Result},
@@ -8,10 +11,52 @@ use mpris_server::{
 use tokio::sync::mpsc::Sender;
 
 use super::Messages;
+use crate::tracks::TrackInfo;
 
 const ERROR: fdo::Error = fdo::Error::Failed(String::new());
 
+/// Creates the MPRIS metadata for `track`, which is empty if nothing is playing.
+///
+/// The artist is only included if the name follows the "Artist - Title" convention,
+/// since a blank artist would look worse in most clients than a missing one.
+pub fn metadata(track: Option<&TrackInfo>) -> Metadata {
+    let Some(track) = track else {
+        return Metadata::new();
+    };
+
+    let mut metadata = Metadata::new();
+    match track.split_artist() {
+        Some((artist, title)) => {
+            metadata.set_artist(Some([artist]));
+            metadata.set_title(Some(title));
+        }
+        None => metadata.set_title(Some(track.name.clone())),
+    }
+
+    metadata.set_length(
+        track
+            .duration
+            .map(|x| Time::from_micros(x.as_micros() as i64)),
+    );
+
+    metadata
+}
+
+/// Gets whether `player` is playing, paused, or stopped, which is when nothing is loaded.
+pub fn playback_status(player: &super::Player) -> PlaybackStatus {
+    if !player.current_exists() {
+        PlaybackStatus::Stopped
+    } else if player.sink.is_paused() {
+        PlaybackStatus::Paused
+    } else {
+        PlaybackStatus::Playing
+    }
+}
+
 /// The actual MPRIS server.
+///
+/// This is also how media keys work when the terminal isn't focused, or doesn't report them,
+/// since desktops send them to the MPRIS player through something like `playerctld`.
 pub struct Player {
     pub player: Arc<super::Player>,
     pub sender: Sender<Messages>,
@@ -73,16 +118,16 @@ impl PlayerInterface for Player {
     }
 
     async fn previous(&self) -> fdo::Result<()> {
-        Err(ERROR)
-    }
-
-    async fn pause(&self) -> fdo::Result<()> {
         self.sender
-            .send(Messages::PlayPause)
+            .send(Messages::Previous)
             .await
             .map_err(|_| ERROR)
     }
 
+    async fn pause(&self) -> fdo::Result<()> {
+        self.sender.send(Messages::Pause).await.map_err(|_| ERROR)
+    }
+
     async fn play_pause(&self) -> fdo::Result<()> {
         self.sender
             .send(Messages::PlayPause)
@@ -90,20 +135,45 @@ impl PlayerInterface for Player {
             .map_err(|_| ERROR)
     }
 
+    // lowfi can't really be stopped without quitting, so this just pauses.
     async fn stop(&self) -> fdo::Result<()> {
-        self.play_pause().await
+        self.pause().await
     }
 
     async fn play(&self) -> fdo::Result<()> {
-        self.play_pause().await
+        self.sender.send(Messages::Play).await.map_err(|_| ERROR)
     }
 
-    async fn seek(&self, _offset: Time) -> fdo::Result<()> {
-        Err(ERROR)
+    async fn seek(&self, offset: Time) -> fdo::Result<()> {
+        let position = self.player.elapsed().as_micros() as i64 + offset.as_micros();
+        let position = Duration::from_micros(position.max(0) as u64);
+
+        self.sender
+            .send(Messages::SetPosition(position))
+            .await
+            .map_err(|_| ERROR)
     }
 
-    async fn set_position(&self, _track_id: TrackId, _position: Time) -> fdo::Result<()> {
-        Err(ERROR)
+    async fn set_position(&self, _track_id: TrackId, position: Time) -> fdo::Result<()> {
+        // lowfi doesn't have track IDs, so `_track_id` can't be checked.
+        let Some(duration) = self.player.current.load().as_ref().and_then(|x| x.duration) else {
+            return Ok(());
+        };
+
+        // Positions outside of the track should be ignored, according to the spec.
+        if position.as_micros() < 0 {
+            return Ok(());
+        }
+
+        let position = Duration::from_micros(position.as_micros() as u64);
+        if position > duration {
+            return Ok(());
+        }
+
+        self.sender
+            .send(Messages::SetPosition(position))
+            .await
+            .map_err(|_| ERROR)
     }
 
     async fn open_uri(&self, _uri: String) -> fdo::Result<()> {
@@ -111,13 +181,7 @@ impl PlayerInterface for Player {
     }
 
     async fn playback_status(&self) -> fdo::Result<PlaybackStatus> {
-        Ok(if !self.player.current_exists() {
-            PlaybackStatus::Stopped
-        } else if self.player.sink.is_paused() {
-            PlaybackStatus::Paused
-        } else {
-            PlaybackStatus::Playing
-        })
+        Ok(playback_status(&self.player))
     }
 
     async fn loop_status(&self) -> fdo::Result<LoopStatus> {
@@ -138,46 +202,37 @@ impl PlayerInterface for Player {
     }
 
     async fn shuffle(&self) -> fdo::Result<bool> {
-        Ok(true)
+        Ok(self.player.list.is_shuffled())
     }
 
-    async fn set_shuffle(&self, _shuffle: bool) -> Result<()> {
-        Ok(())
+    async fn set_shuffle(&self, shuffle: bool) -> Result<()> {
+        if shuffle == self.player.list.is_shuffled() {
+            return Ok(());
+        }
+
+        self.sender
+            .send(Messages::ToggleShuffle)
+            .await
+            .map_err(|_| ERROR.into())
     }
 
     async fn metadata(&self) -> fdo::Result<Metadata> {
-        let metadata = match self.player.current.load().as_ref() {
-            Some(track) => {
-                let mut metadata = Metadata::builder().title(track.name.clone()).build();
-
-                metadata.set_length(
-                    track
-                        .duration
-                        .and_then(|x| Some(Time::from_micros(x.as_micros() as i64))),
-                );
-
-                metadata
-            }
-            None => Metadata::new(),
-        };
-
-        Ok(metadata)
+        Ok(metadata(self.player.current.load().as_deref()))
     }
 
     async fn volume(&self) -> fdo::Result<Volume> {
-        Ok(self.player.sink.volume().into())
+        Ok(self.player.volume().into())
     }
 
     async fn set_volume(&self, volume: Volume) -> Result<()> {
-        self.player.set_volume(volume as f32);
-
-        Ok(())
+        self.sender
+            .send(Messages::SetVolume(volume as f32))
+            .await
+            .map_err(|_| ERROR.into())
     }
 
     async fn position(&self) -> fdo::Result<Time> {
-        Ok(Time::from_micros(
-            self.player.sink.get_pos().as_micros() as i64
-        ))
+        Ok(Time::from_micros(self.player.elapsed().as_micros() as i64))
     }
 
     async fn minimum_rate(&self) -> fdo::Result<PlaybackRate> {
@@ -193,7 +248,7 @@ impl PlayerInterface for Player {
     }
 
     async fn can_go_previous(&self) -> fdo::Result<bool> {
-        Ok(false)
+        Ok(self.player.has_previous())
     }
 
     async fn can_play(&self) -> fdo::Result<bool> {
@@ -205,7 +260,7 @@ impl PlayerInterface for Player {
     }
 
     async fn can_seek(&self) -> fdo::Result<bool> {
-        Ok(false)
+        Ok(self.player.current_exists() && self.player.seekable.load(Ordering::Relaxed))
     }
 
     async fn can_control(&self) -> fdo::Result<bool> {
@@ -0,0 +1,54 @@
+//! Contains the [Notifier], which sends a desktop notification
+//! whenever a new track starts playing.
+
+#[cfg(all(unix, not(target_os = "macos")))]
+use std::sync::atomic::{AtomicU32, Ordering};
+
+use notify_rust::Notification;
+
+/// Sends the track change notifications.
+///
+/// On Linux (and the BSDs), every notification replaces the last one instead of stacking,
+/// which isn't possible on other platforms.
+pub struct Notifier {
+    /// The ID of the last notification, which is `0` if there hasn't been one yet.
+    #[cfg(all(unix, not(target_os = "macos")))]
+    id: AtomicU32,
+}
+
+impl Notifier {
+    /// Creates a new [Notifier], which hasn't sent anything yet.
+    pub fn new() -> Self {
+        Self {
+            #[cfg(all(unix, not(target_os = "macos")))]
+            id: AtomicU32::new(0),
+        }
+    }
+
+    /// Shows a notification for the track called `name`.
+    ///
+    /// This blocks, so it should be called with [tokio::task::spawn_blocking].
+    /// Any errors are ignored.
+    pub fn send(&self, name: &str) {
+        let mut notification = Notification::new();
+        notification
+            .appname("lowfi")
+            .summary("now playing")
+            .body(name);
+
+        #[cfg(all(unix, not(target_os = "macos")))]
+        {
+            let id = self.id.load(Ordering::Relaxed);
+            if id != 0 {
+                notification.id(id);
+            }
+
+            if let Ok(handle) = notification.show() {
+                self.id.store(handle.id(), Ordering::Relaxed);
+            }
+        }
+
+        #[cfg(not(all(unix, not(target_os = "macos"))))]
+        let _ = notification.show();
+    }
+}
@@ -0,0 +1,268 @@
+//! Keeps track of which source in the [Sink](rodio::Sink) is actually playing,
+//! which is what lets the next track be queued up ahead of time for gapless playback.
+
+use std::{
+    sync::{
+        atomic::{AtomicU64, AtomicU8, Ordering},
+        Arc,
+    },
+    time::Duration,
+};
+
+use rodio::{source::SeekError, Sample, Source};
+use tokio::sync::Notify;
+
+use crate::tracks::{stream::Underruns, DecodedTrack, Track, TrackInfo};
+
+/// The source hasn't started playing yet.
+const WAITING: u8 = 0;
+
+/// The source has started playing, so it can't be cancelled anymore.
+const STARTED: u8 = 1;
+
+/// The source was cancelled before it started, so it'll be skipped over.
+const CANCELLED: u8 = 2;
+
+/// Shared between the player & every [Tracked] source, so that
+/// the player can find out when a source starts or finishes.
+pub struct Signals {
+    /// The identifier which will be given to the next source.
+    next: AtomicU64,
+
+    /// The identifier of the source which most recently started playing.
+    playing: AtomicU64,
+
+    /// The identifier of the source which most recently finished playing.
+    finished: AtomicU64,
+
+    /// Notified whenever a source starts or finishes.
+    pub changed: Notify,
+}
+
+impl Default for Signals {
+    fn default() -> Self {
+        Self {
+            next: AtomicU64::new(1),
+            playing: AtomicU64::new(0),
+            finished: AtomicU64::new(0),
+            changed: Notify::new(),
+        }
+    }
+}
+
+impl Signals {
+    /// Gets a new identifier for a source.
+    pub fn id(&self) -> u64 {
+        self.next.fetch_add(1, Ordering::Relaxed)
+    }
+
+    /// The identifier of the source which most recently started playing.
+    pub fn playing(&self) -> u64 {
+        self.playing.load(Ordering::Acquire)
+    }
+
+    /// The identifier of the source which most recently finished playing.
+    pub fn finished(&self) -> u64 {
+        self.finished.load(Ordering::Acquire)
+    }
+}
+
+/// Whether a source has started yet, which is what decides if it can still be cancelled.
+///
+/// The [Default] state hasn't started or been cancelled yet.
+#[derive(Clone, Default)]
+pub struct State(Arc<AtomicU8>);
+
+impl State {
+    /// Cancels the source, which only works if it hasn't started yet.
+    pub fn cancel(&self) -> bool {
+        self.0
+            .compare_exchange(WAITING, CANCELLED, Ordering::AcqRel, Ordering::Acquire)
+            .is_ok()
+    }
+
+    /// Marks the source as started, which only works if it hasn't been cancelled.
+    fn start(&self) -> bool {
+        self.0
+            .compare_exchange(WAITING, STARTED, Ordering::AcqRel, Ordering::Acquire)
+            .is_ok()
+    }
+}
+
+/// A source in the sink, which reports to [Signals] when it starts & finishes.
+///
+/// If it's cancelled before it starts, then it'll end right away without reporting anything.
+/// Once the input is over, `gap` worth of silence is played before it counts as finished.
+pub struct Tracked<S> {
+    input: S,
+    id: u64,
+    signals: Arc<Signals>,
+    state: State,
+    gap: Duration,
+
+    /// How many samples of silence are left, which is [None] until the input is over.
+    silence: Option<usize>,
+
+    /// Whether the source has been pulled from yet.
+    started: bool,
+
+    /// Whether the source has reported that it's finished.
+    finished: bool,
+}
+
+impl<S> Tracked<S> {
+    pub fn new(input: S, id: u64, signals: Arc<Signals>, state: State, gap: Duration) -> Self {
+        Self {
+            input,
+            id,
+            signals,
+            state,
+            gap,
+            silence: None,
+            started: false,
+            finished: false,
+        }
+    }
+}
+
+impl<S> Iterator for Tracked<S>
+where
+    S: Source,
+    S::Item: Sample,
+{
+    type Item = S::Item;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.finished {
+            return None;
+        }
+
+        if !self.started {
+            self.started = true;
+            if !self.state.start() {
+                self.finished = true;
+                return None;
+            }
+
+            self.signals.playing.store(self.id, Ordering::Release);
+            self.signals.changed.notify_one();
+        }
+
+        if self.silence.is_none() {
+            if let Some(next) = self.input.next() {
+                return Some(next);
+            }
+
+            let frames = (self.gap.as_secs_f64() * f64::from(self.input.sample_rate())) as usize;
+            self.silence = Some(frames * usize::from(self.input.channels()));
+        }
+
+        match &mut self.silence {
+            Some(0) | None => {
+                self.finished = true;
+                self.signals.finished.store(self.id, Ordering::Release);
+                self.signals.changed.notify_one();
+
+                None
+            }
+            Some(remaining) => {
+                *remaining -= 1;
+                Some(S::Item::zero_value())
+            }
+        }
+    }
+}
+
+impl<S> Source for Tracked<S>
+where
+    S: Source,
+    S::Item: Sample,
+{
+    fn current_frame_len(&self) -> Option<usize> {
+        self.silence.or_else(|| self.input.current_frame_len())
+    }
+
+    fn channels(&self) -> u16 {
+        self.input.channels()
+    }
+
+    fn sample_rate(&self) -> u32 {
+        self.input.sample_rate()
+    }
+
+    fn total_duration(&self) -> Option<Duration> {
+        self.input.total_duration()
+    }
+
+    fn try_seek(&mut self, position: Duration) -> Result<(), SeekError> {
+        self.input.try_seek(position)?;
+
+        // Seeking back during the gap plays the rest of the input again, and then the whole gap after it.
+        self.silence = None;
+        Ok(())
+    }
+}
+
+/// A track which has been appended to the sink after the current one,
+/// and so will start playing as soon as the current one is over.
+pub struct Queued {
+    /// The identifier of the track's source in the sink.
+    pub id: u64,
+
+    /// The raw data of the track, which is needed to play it again.
+    pub track: Track,
+
+    pub info: TrackInfo,
+
+    /// The silence the track plays during underruns, if it's being streamed.
+    pub underruns: Option<Underruns>,
+
+    /// Whether this is the current track again, which is queued up while repeating.
+    pub replay: bool,
+
+    pub state: State,
+}
+
+impl Queued {
+    /// Decodes the track again, since the decoded data was given to the sink.
+    ///
+    /// Even if it's still being streamed, this won't block, since the start of the
+    /// track already had to arrive for it to be decoded the first time around.
+    pub fn restore(&self) -> eyre::Result<(Track, DecodedTrack)> {
+        // `Bytes` is reference counted, so this doesn't actually copy the data.
+        let mut decoded = self.track.clone().decode()?;
+        decoded.info.gain = self.info.gain;
+
+        Ok((self.track.clone(), decoded))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{sync::Arc, time::Duration};
+
+    use rodio::{buffer::SamplesBuffer, Source};
+
+    use super::{Signals, State, Tracked};
+
+    #[test]
+    fn seeking_back_during_the_gap_plays_the_input_again() {
+        // One second of audio at 4 samples per second, followed by a one second gap.
+        let input = SamplesBuffer::new(1, 4, vec![1.0f32; 4]);
+        let signals = Arc::new(Signals::default());
+        let mut tracked = Tracked::new(
+            input,
+            signals.id(),
+            signals,
+            State::default(),
+            Duration::from_secs(1),
+        );
+
+        let start: Vec<f32> = tracked.by_ref().take(6).collect();
+        assert_eq!(start, [1.0, 1.0, 1.0, 1.0, 0.0, 0.0]);
+
+        tracked.try_seek(Duration::ZERO).unwrap();
+        let rest: Vec<f32> = tracked.collect();
+        assert_eq!(rest, [1.0, 1.0, 1.0, 1.0, 0.0, 0.0, 0.0, 0.0]);
+    }
+}
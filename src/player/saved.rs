@@ -0,0 +1,113 @@
+//! Responsible for the files where lowfi saves track identifiers,
+//! which are `bookmarks.txt` & `blocklist.txt`, alongside `volumes.tsv`.
+
+use std::{collections::HashMap, path::PathBuf};
+
+use tokio::{fs, io::AsyncWriteExt};
+
+use crate::play::InitialProperties;
+
+/// The bookmarked tracks, which can be played with `--bookmarks`.
+pub const BOOKMARKS: &str = "bookmarks.txt";
+
+/// The blocked tracks, which will never be played.
+pub const BLOCKLIST: &str = "blocklist.txt";
+
+/// The volumes which bookmarked tracks are played at, where each line
+/// is a percentage & then the identifier, seperated by a tab.
+pub const VOLUMES: &str = "volumes.tsv";
+
+/// Gets the path of one of the saved files, which are in the config directory.
+pub async fn path(name: &str) -> eyre::Result<PathBuf> {
+    Ok(InitialProperties::config().await?.join(name))
+}
+
+/// Reads every identifier in one of the saved files, which is empty if it doesn't exist.
+pub async fn read(name: &str) -> eyre::Result<Vec<String>> {
+    let path = path(name).await?;
+    if !path.exists() {
+        return Ok(Vec::new());
+    }
+
+    Ok(fs::read_to_string(path)
+        .await?
+        .lines()
+        .map(str::trim)
+        .filter(|x| !x.is_empty())
+        .map(String::from)
+        .collect())
+}
+
+/// Adds `id` to one of the saved files, unless it's already there.
+///
+/// This returns whether the track was actually added.
+pub async fn add(name: &str, id: &str) -> eyre::Result<bool> {
+    let path = path(name).await?;
+    let existing = if path.exists() {
+        fs::read_to_string(&path).await?
+    } else {
+        String::new()
+    };
+
+    if existing.lines().any(|x| x.trim() == id) {
+        return Ok(false);
+    }
+
+    // Make sure the new track ends up on its own line, even if the file was edited by hand.
+    let separator = if existing.is_empty() || existing.ends_with('\n') {
+        ""
+    } else {
+        "\n"
+    };
+
+    let mut file = fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path)
+        .await?;
+    file.write_all(format!("{separator}{id}\n").as_bytes())
+        .await?;
+
+    Ok(true)
+}
+
+/// Reads [VOLUMES], as a map from identifiers to volumes, with 1.0 being 100%.
+///
+/// Lines which can't be parsed are skipped, since the file might've been edited by hand.
+pub async fn volumes() -> eyre::Result<HashMap<String, f32>> {
+    let path = path(VOLUMES).await?;
+    if !path.exists() {
+        return Ok(HashMap::new());
+    }
+
+    Ok(fs::read_to_string(path)
+        .await?
+        .lines()
+        .filter_map(|x| {
+            let (volume, id) = x.split_once('\t')?;
+            let volume: f32 = volume.trim().parse().ok()?;
+            Some((id.trim().to_string(), volume / 100.0))
+        })
+        .filter(|(_, volume)| volume.is_finite() && *volume >= 0.0)
+        .collect())
+}
+
+/// Sets the volume of `id` in [VOLUMES], replacing the one it had before.
+pub async fn set_volume(id: &str, volume: f32) -> eyre::Result<()> {
+    let path = path(VOLUMES).await?;
+    let existing = if path.exists() {
+        fs::read_to_string(&path).await?
+    } else {
+        String::new()
+    };
+
+    let mut contents: String = existing
+        .lines()
+        .filter(|x| x.split_once('\t').map(|(_, x)| x.trim()) != Some(id))
+        .map(|x| format!("{x}\n"))
+        .collect();
+    contents.push_str(&format!("{}\t{id}\n", (volume * 100.0).round() as u16));
+
+    fs::write(path, contents).await?;
+    Ok(())
+}
@@ -0,0 +1,271 @@
+//! Contains the [Scrobbler], which submits tracks to Last.fm and/or ListenBrainz.
+
+use std::{
+    path::Path,
+    sync::Mutex,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use eyre::eyre;
+use futures::future::join_all;
+use reqwest::Client;
+use tokio::fs;
+
+use crate::tracks::TrackInfo;
+
+#[cfg(feature = "scrobble")]
+mod lastfm;
+
+#[cfg(feature = "listenbrainz")]
+mod listenbrainz;
+
+/// Tracks are always scrobbled after this much time, regardless of their length.
+const THRESHOLD_MAX: Duration = Duration::from_secs(4 * 60);
+
+/// The most scrobbles that are submitted in one request.
+const BATCH_SIZE: usize = 50;
+
+/// How long lowfi will wait to submit scrobbles when quitting.
+const QUIT_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// The artist used for tracks that don't follow the "Artist - Title" convention.
+const DEFAULT_ARTIST: &str = "Lofi Girl";
+
+/// A track which has been listened to, and is waiting to be scrobbled.
+#[derive(Debug, Clone)]
+pub struct Scrobble {
+    /// The artist from the track's name, which is [None] if it doesn't have one.
+    artist: Option<String>,
+    title: String,
+
+    /// When the track started playing, as a UNIX timestamp.
+    timestamp: u64,
+}
+
+impl Scrobble {
+    /// Creates a scrobble of `info`, which started playing just now.
+    fn new(info: &TrackInfo) -> Self {
+        let (artist, title) = info
+            .split_artist()
+            .map_or((None, info.name.as_str()), |(x, y)| (Some(x), y));
+
+        Self {
+            artist: artist.map(String::from),
+            title: title.to_owned(),
+            timestamp: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .unwrap_or_default()
+                .as_secs(),
+        }
+    }
+
+    /// Gets the artist of the track, using `fallback` if the name didn't have one.
+    fn artist<'a>(&'a self, fallback: &'a str) -> &'a str {
+        self.artist.as_deref().unwrap_or(fallback)
+    }
+}
+
+/// Gets `key` from `section` of `scrobble.toml`, which has to be a string.
+fn get(section: &toml::Table, name: &str, key: &str) -> eyre::Result<String> {
+    section
+        .get(key)
+        .and_then(|x| x.as_str())
+        .map(String::from)
+        .ok_or(eyre!("`{key}` is missing from [{name}] in scrobble.toml"))
+}
+
+/// Gets the artist to use for tracks without one from `section`, or [DEFAULT_ARTIST].
+fn artist(section: &toml::Table) -> String {
+    section
+        .get("artist")
+        .and_then(|x| x.as_str())
+        .unwrap_or(DEFAULT_ARTIST)
+        .to_owned()
+}
+
+/// One of the services which tracks can be scrobbled to.
+enum Service {
+    #[cfg(feature = "scrobble")]
+    LastFm(lastfm::LastFm),
+
+    #[cfg(feature = "listenbrainz")]
+    ListenBrainz(listenbrainz::ListenBrainz),
+}
+
+impl Service {
+    /// Sends a "now playing" update for `scrobble`.
+    async fn now_playing(&self, client: &Client, scrobble: &Scrobble) -> eyre::Result<()> {
+        match self {
+            #[cfg(feature = "scrobble")]
+            Self::LastFm(x) => x.now_playing(client, scrobble).await,
+
+            #[cfg(feature = "listenbrainz")]
+            Self::ListenBrainz(x) => x.now_playing(client, scrobble).await,
+        }
+    }
+
+    /// Submits all of `scrobbles` in a single request.
+    async fn submit(&self, client: &Client, scrobbles: &[Scrobble]) -> eyre::Result<()> {
+        match self {
+            #[cfg(feature = "scrobble")]
+            Self::LastFm(x) => x.submit(client, scrobbles).await,
+
+            #[cfg(feature = "listenbrainz")]
+            Self::ListenBrainz(x) => x.submit(client, scrobbles).await,
+        }
+    }
+}
+
+/// A [Service], alongside the scrobbles which haven't been successfully submitted to it yet.
+struct Backend {
+    service: Service,
+    queue: Mutex<Vec<Scrobble>>,
+}
+
+impl Backend {
+    /// Tries to submit the queued scrobbles, putting them back in the queue if it fails.
+    async fn flush(&self, client: &Client) {
+        let batch: Vec<Scrobble> = {
+            let mut queue = self.queue.lock().unwrap();
+            let len = queue.len().min(BATCH_SIZE);
+            queue.drain(..len).collect()
+        };
+
+        if batch.is_empty() {
+            return;
+        }
+
+        if self.service.submit(client, &batch).await.is_err() {
+            let mut queue = self.queue.lock().unwrap();
+            queue.splice(0..0, batch);
+        }
+    }
+}
+
+/// Submits "now playing" updates and scrobbles to every service
+/// which has been set up, which can be Last.fm, ListenBrainz, or both.
+///
+/// Each service is configured in its own section of `scrobble.toml`, and
+/// scrobbles which fail to submit are queued up & retried later.
+pub struct Scrobbler {
+    /// The client which is used for all of the requests.
+    client: Client,
+
+    /// Every service that's been set up.
+    backends: Vec<Backend>,
+
+    /// The track which is currently playing, and its duration.
+    current: Mutex<Option<(Scrobble, Option<Duration>)>>,
+
+    /// How much of a track needs to be played for it to be scrobbled, from `--scrobble-threshold`.
+    threshold: f32,
+
+    /// Tracks shorter than this are never scrobbled, from `--scrobble-min-seconds`.
+    min_length: Duration,
+}
+
+impl Scrobbler {
+    /// Loads the scrobbler from `scrobble.toml` in the config directory.
+    ///
+    /// This is [None] if the file doesn't exist or none of the services are in it,
+    /// since scrobbling should only happen if it's been explicitly set up.
+    pub async fn load(
+        config: &Path,
+        client: Client,
+        threshold: u8,
+        min_seconds: u64,
+    ) -> eyre::Result<Option<Self>> {
+        let path = config.join("scrobble.toml");
+        if !path.exists() {
+            return Ok(None);
+        }
+
+        let table: toml::Table = fs::read_to_string(path)
+            .await?
+            .parse()
+            .map_err(|error| eyre!("scrobble.toml is invalid: {error}"))?;
+
+        let section = |name: &str| table.get(name).and_then(|x| x.as_table());
+        let mut services = Vec::new();
+
+        #[cfg(feature = "scrobble")]
+        if let Some(section) = section("lastfm") {
+            services.push(Service::LastFm(lastfm::LastFm::load(section)?));
+        }
+
+        #[cfg(feature = "listenbrainz")]
+        if let Some(section) = section("listenbrainz") {
+            services.push(Service::ListenBrainz(listenbrainz::ListenBrainz::load(
+                section,
+            )?));
+        }
+
+        if services.is_empty() {
+            return Ok(None);
+        }
+
+        Ok(Some(Self {
+            client,
+            backends: services
+                .into_iter()
+                .map(|service| Backend {
+                    service,
+                    queue: Mutex::new(Vec::new()),
+                })
+                .collect(),
+            current: Mutex::new(None),
+            threshold: f32::from(threshold) / 100.0,
+            min_length: Duration::from_secs(min_seconds),
+        }))
+    }
+
+    /// Records that `info` just started playing, and sends a "now playing" update.
+    pub async fn start(&self, info: &TrackInfo) {
+        let scrobble = Scrobble::new(info);
+        *self.current.lock().unwrap() = Some((scrobble.clone(), info.duration));
+
+        // "Now playing" updates are only relevant right now, so they're never retried.
+        join_all(
+            self.backends
+                .iter()
+                .map(|x| x.service.now_playing(&self.client, &scrobble)),
+        )
+        .await;
+    }
+
+    /// Records that the current track stopped playing after `elapsed`.
+    ///
+    /// If enough of it was played, then it'll be queued up to be scrobbled.
+    /// This shouldn't block, so the actual submission is done in [Scrobbler::flush].
+    pub fn finish(&self, elapsed: Duration) {
+        let Some((scrobble, duration)) = self.current.lock().unwrap().take() else {
+            return;
+        };
+
+        if duration.is_some_and(|x| x < self.min_length) {
+            return;
+        }
+
+        let threshold = duration.map_or(THRESHOLD_MAX, |x| {
+            x.mul_f32(self.threshold).min(THRESHOLD_MAX)
+        });
+        if elapsed >= threshold {
+            for backend in &self.backends {
+                backend.queue.lock().unwrap().push(scrobble.clone());
+            }
+        }
+    }
+
+    /// Tries to submit all of the queued scrobbles to each service.
+    pub async fn flush(&self) {
+        join_all(self.backends.iter().map(|x| x.flush(&self.client))).await;
+    }
+
+    /// Finishes the current track and tries to submit everything before lowfi quits.
+    ///
+    /// This gives up after [QUIT_TIMEOUT], so that quitting isn't held up by a bad connection.
+    pub async fn quit(&self, elapsed: Duration) {
+        self.finish(elapsed);
+        let _ = tokio::time::timeout(QUIT_TIMEOUT, self.flush()).await;
+    }
+}
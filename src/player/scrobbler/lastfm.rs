@@ -0,0 +1,98 @@
+//! Submits scrobbles to Last.fm, which is configured in `[lastfm]`.
+
+use std::collections::BTreeMap;
+
+use eyre::bail;
+use reqwest::Client;
+
+use super::{artist, get, Scrobble};
+
+/// The URL of the Last.fm API.
+const API: &str = "https://ws.audioscrobbler.com/2.0/";
+
+/// The credentials for Last.fm.
+pub struct LastFm {
+    api_key: String,
+    secret: String,
+    session: String,
+
+    /// The artist to use when a track's name doesn't have one.
+    artist: String,
+}
+
+impl LastFm {
+    /// Loads the credentials from the `[lastfm]` section of `scrobble.toml`.
+    pub fn load(section: &toml::Table) -> eyre::Result<Self> {
+        Ok(Self {
+            api_key: get(section, "lastfm", "api_key")?,
+            secret: get(section, "lastfm", "secret")?,
+            session: get(section, "lastfm", "session")?,
+            artist: artist(section),
+        })
+    }
+
+    /// Sends a signed request, with `params` being everything except the authentication.
+    async fn send(
+        &self,
+        client: &Client,
+        method: &str,
+        mut params: BTreeMap<String, String>,
+    ) -> eyre::Result<()> {
+        params.insert(String::from("method"), method.to_owned());
+        params.insert(String::from("api_key"), self.api_key.clone());
+        params.insert(String::from("sk"), self.session.clone());
+
+        // The signature is every parameter concatenated in alphabetical order, followed by the secret.
+        let mut signature: String = params.iter().map(|(k, v)| format!("{k}{v}")).collect();
+        signature.push_str(&self.secret);
+        params.insert(
+            String::from("api_sig"),
+            format!("{:x}", md5::compute(signature)),
+        );
+        params.insert(String::from("format"), String::from("json"));
+
+        let response = client.post(API).form(&params).send().await?;
+        let response = response.error_for_status()?.text().await?;
+        let response: serde_json::Value = serde_json::from_str(&response)?;
+
+        // Last.fm will sometimes respond with a successful status, but still include an error.
+        // The response repeats the track's name, so only the top level is checked.
+        if let Some(code) = response.get("error") {
+            let message = response.get("message").and_then(|x| x.as_str());
+            bail!(
+                "last.fm returned error {code}: {}",
+                message.unwrap_or("no message")
+            );
+        }
+
+        Ok(())
+    }
+
+    /// Sends a "now playing" update for `scrobble`.
+    pub async fn now_playing(&self, client: &Client, scrobble: &Scrobble) -> eyre::Result<()> {
+        let params = BTreeMap::from([
+            (
+                String::from("artist"),
+                scrobble.artist(&self.artist).to_owned(),
+            ),
+            (String::from("track"), scrobble.title.clone()),
+        ]);
+
+        self.send(client, "track.updateNowPlaying", params).await
+    }
+
+    /// Submits all of `scrobbles` at once.
+    pub async fn submit(&self, client: &Client, scrobbles: &[Scrobble]) -> eyre::Result<()> {
+        let mut params = BTreeMap::new();
+        for (i, scrobble) in scrobbles.iter().enumerate() {
+            params.insert(
+                format!("artist[{i}]"),
+                scrobble.artist(&self.artist).to_owned(),
+            );
+            params.insert(format!("track[{i}]"), scrobble.title.clone());
+            params.insert(format!("timestamp[{i}]"), scrobble.timestamp.to_string());
+        }
+
+        self.send(client, "track.scrobble", params).await
+    }
+}
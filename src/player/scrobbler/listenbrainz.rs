@@ -0,0 +1,79 @@
+//! Submits listens to ListenBrainz, which is configured in `[listenbrainz]`.
+
+use reqwest::{header::CONTENT_TYPE, Client};
+use serde_json::{json, Value};
+
+use super::{artist, get, Scrobble};
+
+/// The endpoint of the ListenBrainz API which listens are submitted to.
+const API: &str = "https://api.listenbrainz.org/1/submit-listens";
+
+/// The user token for ListenBrainz.
+pub struct ListenBrainz {
+    token: String,
+
+    /// The artist to use when a track's name doesn't have one.
+    artist: String,
+}
+
+impl ListenBrainz {
+    /// Loads the token from the `[listenbrainz]` section of `scrobble.toml`.
+    pub fn load(section: &toml::Table) -> eyre::Result<Self> {
+        Ok(Self {
+            token: get(section, "listenbrainz", "token")?,
+            artist: artist(section),
+        })
+    }
+
+    /// Gets the metadata of `scrobble`, which is the same for every type of listen.
+    fn metadata(&self, scrobble: &Scrobble) -> Value {
+        json!({
+            "artist_name": scrobble.artist(&self.artist),
+            "track_name": scrobble.title,
+            "additional_info": { "media_player": "lowfi" },
+        })
+    }
+
+    /// Submits a payload with a `listen_type` of `kind`.
+    async fn send(&self, client: &Client, kind: &str, payload: Vec<Value>) -> eyre::Result<()> {
+        let body = json!({ "listen_type": kind, "payload": payload });
+
+        client
+            .post(API)
+            .header("Authorization", format!("Token {}", self.token))
+            .header(CONTENT_TYPE, "application/json")
+            .body(body.to_string())
+            .send()
+            .await?
+            .error_for_status()?;
+
+        Ok(())
+    }
+
+    /// Sends a "playing now" listen for `scrobble`.
+    pub async fn now_playing(&self, client: &Client, scrobble: &Scrobble) -> eyre::Result<()> {
+        let payload = vec![json!({ "track_metadata": self.metadata(scrobble) })];
+        self.send(client, "playing_now", payload).await
+    }
+
+    /// Submits all of `scrobbles` at once, which is a "single" listen if there's only one.
+    pub async fn submit(&self, client: &Client, scrobbles: &[Scrobble]) -> eyre::Result<()> {
+        let payload = scrobbles
+            .iter()
+            .map(|x| {
+                json!({
+                    "listened_at": x.timestamp,
+                    "track_metadata": self.metadata(x),
+                })
+            })
+            .collect();
+
+        let kind = if scrobbles.len() == 1 {
+            "single"
+        } else {
+            "import"
+        };
+
+        self.send(client, kind, payload).await
+    }
+}
@@ -1,174 +1,726 @@
 //! The module which manages all user interface, including inputs.
 
+#[cfg(unix)]
+use std::path::PathBuf;
 use std::{
     io::stdout,
-    sync::{
-        atomic::{AtomicUsize, Ordering},
-        Arc,
-    },
+    sync::{atomic::Ordering, Arc, Mutex},
     time::Duration,
 };
 
 use crate::Args;
 
 use crossterm::{
-    cursor::{Hide, MoveTo, MoveToColumn, MoveUp, Show},
+    cursor::{Hide, MoveDown, MoveTo, MoveToColumn, MoveUp, Show},
     event::{
-        self, EventStream, KeyCode, KeyModifiers, KeyboardEnhancementFlags,
-        PopKeyboardEnhancementFlags, PushKeyboardEnhancementFlags,
+        self, DisableFocusChange, EnableFocusChange, EventStream, KeyCode, KeyEvent, KeyEventKind,
+        KeyboardEnhancementFlags, PopKeyboardEnhancementFlags, PushKeyboardEnhancementFlags,
     },
     style::{Print, Stylize},
-    terminal::{
-        self, Clear, ClearType, EnterAlternateScreen,
-        LeaveAlternateScreen, size,
-    },
+    terminal::{self, size, Clear, ClearType, EnterAlternateScreen, LeaveAlternateScreen},
 };
 
 use futures::{FutureExt, StreamExt};
 use lazy_static::lazy_static;
-use tokio::{sync::mpsc::Sender, task, time::sleep};
+use tokio::{
+    select,
+    sync::mpsc::Sender,
+    task,
+    time::{sleep, sleep_until, Instant},
+};
 
 use super::{Messages, Player};
+use crate::{format::format_long_duration, tracks::TrackInfo};
 
 mod components;
+pub mod keybinds;
+pub mod theme;
 
-/// The app will scale to the width of the terminal, up to the max. If width
-/// deteection fails, use the fallback.
+use keybinds::Keybinds;
+use theme::{paint, BorderStyle, Theme};
+
+/// The app will scale to the width of the terminal, up to the max, which can be
+/// changed with `--width`. If width deteection fails, use the fallback.
 const MAX_WIDTH: usize = 73;
 const FALLBACK_WIDTH: usize = 27;
 
-/// Self explanitory.
-const FPS: usize = 12;
+/// The narrowest that the menu can be drawn. Anything narrower
+/// than this will only show the name of the track on a single line.
+const MIN_WIDTH: usize = 20;
+
+/// How often the progress bar ticks forward, which only redraws the row that it's on.
+const TICK: Duration = Duration::from_secs(1);
+
+/// How long after each second of the track the progress bar ticks, since the
+/// position of the sink is only updated every few milliseconds.
+const TICK_SLACK: Duration = Duration::from_millis(10);
+
+/// How often the whole interface is redrawn if nothing else has happened.
+const IDLE: Duration = Duration::from_secs(30);
+
+/// How long it takes a long track name to scroll by one character.
+const MARQUEE_STEP: Duration = Duration::from_millis(250);
+
+/// How many frequency bands are shown by the visualizer.
+#[cfg(feature = "visualizer")]
+const VISUALIZER_BANDS: usize = 8;
+
+/// The narrowest that the menu can be while still fitting the visualizer.
+#[cfg(feature = "visualizer")]
+const VISUALIZER_MIN_WIDTH: usize = 40;
 
 /// How long the audio bar will be visible for when audio is adjusted.
-/// This is in frames.
-const AUDIO_BAR_DURATION: usize = 10;
+const AUDIO_BAR_DURATION: Duration = Duration::from_secs(1);
+
+/// How long the audio bar stays visible for when the volume is changed again while it's still showing,
+/// like when a fine adjustment key is being pressed over & over, so that it doesn't vanish in between.
+const AUDIO_BAR_HOLD: Duration = Duration::from_secs(2);
+
+/// The message which is shown after playback was paused because the output stream stopped,
+/// which is followed by the key to resume with, see [stalled].
+const STALLED: &str = "audio stopped";
+
+/// The message which is shown when quitting needs to be confirmed, with `--confirm-quit`.
+const CONFIRM_QUIT: &str = "press y to quit";
 
-/// How long to wait in between frames.
-/// This is fairly arbitrary, but an ideal value should be enough to feel
-/// snappy but not require too many resources.
-const FRAME_DELTA: f32 = 1.0 / FPS as f32;
+/// How many times further than a single press each repeat of a held seek key goes, alongside
+/// how long it has to have been held for, so it never goes less far than the key usually would.
+/// Repeats are only told apart from presses if the terminal supports it.
+const SEEK_ACCELERATION: [(Duration, i64); 3] = [
+    (Duration::ZERO, 1),
+    (Duration::from_secs(1), 2),
+    (Duration::from_secs(2), 4),
+];
+
+/// How long the volume will fade out for before the sleep timer quits.
+const SLEEP_FADE: Duration = Duration::from_secs(30);
 
 lazy_static! {
-    /// The volume timer, which controls how long the volume display should
-    /// show up and when it should disappear.
-    static ref VOLUME_TIMER: AtomicUsize = AtomicUsize::new(0);
+    /// When the volume was last changed, which controls how long the volume
+    /// display should show up and when it should disappear, alongside how long it's shown for.
+    static ref VOLUME_CHANGED: Mutex<Option<(Instant, Duration)>> = Mutex::new(None);
 }
 
-async fn input(sender: Sender<Messages>) -> eyre::Result<()> {
+/// Resets `VOLUME_CHANGED`, so that the UI thread will know that it should show the audio bar.
+///
+/// This is called by the player whenever it changes the volume, no matter where the change came from.
+pub fn volume_changed() {
+    let mut changed = VOLUME_CHANGED.lock().unwrap();
+    let showing = changed.is_some_and(|(x, shown)| x.elapsed() < shown);
+    let shown = if showing {
+        AUDIO_BAR_HOLD
+    } else {
+        AUDIO_BAR_DURATION
+    };
+
+    *changed = Some((Instant::now(), shown));
+}
+
+/// Handles all of the keyboard input, using `keybinds` to figure
+/// out which [Messages] to send.
+///
+/// While the help menu is open, any key other than ctrl+c will just close it.
+/// This also redraws the interface whenever the terminal is resized.
+///
+/// If `confirm` is true, then quitting has to be confirmed with `y`, except with ctrl+c.
+/// Holding down a seek key will also seek further the longer it's held, see [SEEK_ACCELERATION].
+///
+/// With `unfocus`, losing focus pauses, and getting it back resumes, but only if it was playing beforehand.
+async fn input(
+    player: Arc<Player>,
+    sender: Sender<Messages>,
+    keybinds: Keybinds,
+    confirm: bool,
+    unfocus: bool,
+) -> eyre::Result<()> {
     let mut reader = EventStream::new();
 
+    // Whether losing focus is what paused the player, so that it's only resumed if so.
+    let mut unfocused = false;
+
+    // The seek key which is being held down, alongside when it was pressed.
+    let mut held: Option<(KeyCode, Instant)> = None;
+
     loop {
-        let Some(Ok(event::Event::Key(event))) = reader.next().fuse().await else {
-            continue;
-        };
+        let event = match reader.next().fuse().await {
+            Some(Ok(event::Event::Key(event))) => event,
+            Some(Ok(event::Event::Resize(..))) => {
+                player.redraw.notify_one();
+                continue;
+            }
+            Some(Ok(event::Event::FocusLost)) if unfocus => {
+                if !player.sink.is_paused() {
+                    unfocused = true;
+                    sender.send(Messages::Pause).await?;
+                }
 
-        let messages = match event.code {
-            // Arrow key volume controls.
-            KeyCode::Up => Messages::ChangeVolume(0.1),
-            KeyCode::Right => Messages::ChangeVolume(0.01),
-            KeyCode::Down => Messages::ChangeVolume(-0.1),
-            KeyCode::Left => Messages::ChangeVolume(-0.01),
-            KeyCode::Char(character) => match character.to_ascii_lowercase() {
-                // Ctrl+C
-                'c' if event.modifiers == KeyModifiers::CONTROL => Messages::Quit,
-
-                // Quit
-                'q' => Messages::Quit,
-
-                // Skip/Next
-                's' | 'n' => Messages::Next,
-
-                // Pause
-                'p' => Messages::PlayPause,
-
-                // Volume up & down
-                '+' | '=' => Messages::ChangeVolume(0.1),
-                '-' | '_' => Messages::ChangeVolume(-0.1),
-
-                _ => continue,
-            },
-            // Media keys
-            KeyCode::Media(media) => match media {
-                event::MediaKeyCode::Play => Messages::PlayPause,
-                event::MediaKeyCode::Pause => Messages::PlayPause,
-                event::MediaKeyCode::PlayPause => Messages::PlayPause,
-                event::MediaKeyCode::Stop => Messages::PlayPause,
-                event::MediaKeyCode::TrackNext => Messages::Next,
-                event::MediaKeyCode::LowerVolume => Messages::ChangeVolume(-0.1),
-                event::MediaKeyCode::RaiseVolume => Messages::ChangeVolume(0.1),
-                event::MediaKeyCode::MuteVolume => Messages::ChangeVolume(-1.0),
-                _ => continue,
-            },
+                continue;
+            }
+            Some(Ok(event::Event::FocusGained)) if unfocus => {
+                if std::mem::take(&mut unfocused) {
+                    sender.send(Messages::Play).await?;
+                }
+
+                continue;
+            }
             _ => continue,
         };
 
-        // If it's modifying the volume, then we'll set the `VOLUME_TIMER` to 1
-        // so that the UI thread will know that it should show the audio bar.
-        if let Messages::ChangeVolume(_) = messages {
-            VOLUME_TIMER.store(1, Ordering::Relaxed);
+        // Releases are only reported with keyboard enhancement, and only matter for seeking.
+        if event.kind == KeyEventKind::Release {
+            if held.is_some_and(|(code, _)| code == event.code) {
+                held = None;
+            }
+
+            continue;
+        }
+
+        if player.help.load(Ordering::Relaxed)
+            && KeyEvent::new(event.code, event.modifiers) != keybinds::CTRL_C
+        {
+            sender.send(Messages::ToggleHelp).await?;
+            continue;
+        }
+
+        // The confirmation lasts as long as its message is shown, and then times out by itself.
+        if confirm && event.code == KeyCode::Char('y') && player.flashed() == Some(CONFIRM_QUIT) {
+            sender.send(Messages::Quit).await?;
+            continue;
+        }
+
+        let Some(mut messages) = keybinds.get(event.code, event.modifiers) else {
+            continue;
+        };
+
+        if let Messages::Seek(seconds) = messages {
+            match held.filter(|(code, _)| *code == event.code) {
+                Some((_, pressed)) if event.kind == KeyEventKind::Repeat => {
+                    let factor = SEEK_ACCELERATION
+                        .iter()
+                        .rev()
+                        .find(|(after, _)| pressed.elapsed() >= *after)
+                        .map_or(1, |(_, factor)| *factor);
+
+                    messages = Messages::Seek(seconds.saturating_mul(factor));
+                }
+                _ => held = Some((event.code, Instant::now())),
+            }
+        }
+
+        if confirm
+            && messages == Messages::Quit
+            && KeyEvent::new(event.code, event.modifiers) != keybinds::CTRL_C
+        {
+            player.flash(CONFIRM_QUIT);
+            continue;
         }
 
         sender.send(messages).await?;
     }
 }
 
+/// The sleep timer, which will gradually fade out the volume and then quit once `deadline` is reached.
+///
+/// See [Player::fade_out] for how the fade works.
+async fn sleep_timer(
+    player: Arc<Player>,
+    sender: Sender<Messages>,
+    deadline: Instant,
+) -> eyre::Result<()> {
+    sleep_until(deadline - SLEEP_FADE).await;
+    player.fade_out(SLEEP_FADE).await;
+
+    sleep_until(deadline).await;
+    sender.send(Messages::Quit).await?;
+
+    Ok(())
+}
+
+/// Waits for lowfi to be asked to stop by something other than the user, like `kill`
+/// or a service manager, and then quits the same way as if `q` had been pressed.
+async fn terminated(sender: Sender<Messages>) -> eyre::Result<()> {
+    signalled().await?;
+    sender.send(Messages::Quit).await?;
+
+    Ok(())
+}
+
+/// Waits for one of the signals that [terminated] quits on.
+///
+/// On unix this is `SIGTERM` or `SIGHUP`, which is sent when a terminal is closed,
+/// and on Windows it's ctrl+break, since there isn't really an equivalent.
+/// ctrl+c is also included, since it's a signal instead of a key with `--headless`.
+async fn signalled() -> eyre::Result<()> {
+    let interrupted = tokio::signal::ctrl_c();
+
+    #[cfg(unix)]
+    {
+        use tokio::signal::unix::{signal, SignalKind};
+
+        let mut terminate = signal(SignalKind::terminate())?;
+        let mut hangup = signal(SignalKind::hangup())?;
+        select! {
+            _ = terminate.recv() => (),
+            _ = hangup.recv() => (),
+            _ = interrupted => (),
+        }
+    }
+
+    #[cfg(windows)]
+    {
+        let mut ctrl_break = tokio::signal::windows::ctrl_break()?;
+        select! {
+            _ = ctrl_break.recv() => (),
+            _ = interrupted => (),
+        }
+    }
+
+    #[cfg(not(any(unix, windows)))]
+    interrupted.await?;
+
+    Ok(())
+}
+
+/// Works out the width of the menu's contents from the amount of `columns` in the terminal,
+/// leaving room for the `border`. If the terminal's size isn't known, then this is [FALLBACK_WIDTH].
+///
+/// `max` is the widest the menu can be, which is usually [MAX_WIDTH].
+fn width(columns: Option<u16>, max: usize, border: BorderStyle) -> usize {
+    columns.map_or(FALLBACK_WIDTH, |x| {
+        usize::from(x).saturating_sub(border.columns()).min(max)
+    })
+}
+
+/// Makes sure that nothing from `line` carries over into whatever's drawn after it,
+/// which isn't needed when the theme is plain, since nothing is styled in the first place.
+fn reset(line: &str, theme: &Theme) -> String {
+    if theme.plain {
+        line.to_owned()
+    } else {
+        line.reset().to_string()
+    }
+}
+
+/// Puts a single line of the menu between the sides of the border, if there is one.
+fn framed(line: &str, theme: &Theme) -> String {
+    match theme.border_style.glyphs() {
+        Some([.., side]) => {
+            let side = paint(side.stylize(), theme.border);
+            format!("{side} {} {side}", reset(line, theme))
+        }
+        None => reset(line, theme),
+    }
+}
+
+/// Draws `menu` inside of its border, and then moves the cursor back to the top
+/// so that the next frame is drawn over this one.
+fn draw(menu: Vec<String>, width: usize, theme: &Theme) -> eyre::Result<()> {
+    // Formats the menu properly
+    let menu: Vec<String> = menu
+        .into_iter()
+        .map(|x| framed(&x, theme) + "\r\n")
+        .collect();
+
+    // Without a border, the cursor just ends up on the line after the menu.
+    let (top, bottom) = match theme.border_style.glyphs() {
+        Some([a, b, c, d, line, _]) => {
+            let line = line.to_string().repeat(width + 2);
+            (
+                paint(format!("{a}{line}{b}\r\n").stylize(), theme.border).to_string(),
+                paint(format!("{c}{line}{d}").stylize(), theme.border).to_string(),
+            )
+        }
+        None => (String::new(), String::new()),
+    };
+
+    crossterm::execute!(
+        stdout(),
+        Clear(ClearType::FromCursorDown),
+        MoveToColumn(0),
+        Print(top),
+        Print(menu.join("")),
+        Print(bottom),
+        MoveToColumn(0),
+        MoveUp(menu.len() as u16 + theme.border_style.rows())
+    )?;
+
+    Ok(())
+}
+
+/// Builds the message which is shown after the output stream stopped, from [STALLED]
+/// and the first key which is bound to playing & pausing, if there are any.
+fn stalled(keybinds: &Keybinds) -> String {
+    let key = keybinds
+        .names()
+        .iter()
+        .find(|(action, _)| *action == "play_pause")
+        .and_then(|(_, keys)| keys.first());
+
+    match key {
+        Some(key) => format!("{STALLED}, press {key}"),
+        None => String::from(STALLED),
+    }
+}
+
+/// Whether `event` is a press of ctrl+c or one of the keys bound to [Messages::Quit],
+/// which is used before [input] has taken over, since there's no [Player] to send to yet.
+fn quits(event: &KeyEvent, keybinds: &Keybinds) -> bool {
+    event.kind != KeyEventKind::Release
+        && (KeyEvent::new(event.code, event.modifiers) == keybinds::CTRL_C
+            || keybinds.get(event.code, event.modifiers) == Some(Messages::Quit))
+}
+
+/// Draws a menu which just says that lowfi is loading, while the player is still being
+/// set up, which is mostly waiting for the track list to be fetched.
+///
+/// This only finishes once the user quits, so it should be dropped when the [Player] is ready.
+pub async fn loading(
+    max: usize,
+    minimalist: bool,
+    theme: &Theme,
+    keybinds: &Keybinds,
+) -> eyre::Result<()> {
+    let mut reader = EventStream::new();
+    let started = Instant::now();
+
+    loop {
+        let width = width(size().ok().map(|x| x.0), max, theme.border_style);
+        if width >= MIN_WIDTH {
+            let scroll = started.elapsed().as_millis() / MARQUEE_STEP.as_millis();
+            let mut menu = vec![
+                components::loading(width, scroll as usize),
+                " ".repeat(width),
+            ];
+
+            if !minimalist {
+                menu.push(components::controls(width, false, theme));
+            }
+
+            draw(menu, width, theme)?;
+        }
+
+        select! {
+            () = sleep(MARQUEE_STEP) => (),
+            Some(Ok(event::Event::Key(event))) = reader.next().fuse() => {
+                if quits(&event, keybinds) {
+                    return Ok(());
+                }
+            }
+        }
+    }
+}
+
+/// Counts down from `seconds` before anything starts playing, which is from `--countdown`.
+///
+/// This is [false] if the countdown was cancelled with ctrl+c, the quit key or one of the
+/// signals from [signalled], in which case lowfi should quit.
+pub async fn countdown(
+    seconds: u64,
+    max: usize,
+    minimalist: bool,
+    theme: &Theme,
+    keybinds: &Keybinds,
+) -> eyre::Result<bool> {
+    let mut reader = EventStream::new();
+    let mut text = String::from("starting in");
+
+    let signalled = signalled();
+    tokio::pin!(signalled);
+
+    for remaining in (1..=seconds).rev() {
+        text.push_str(&if remaining == seconds {
+            format!(" {remaining}")
+        } else {
+            format!("…{remaining}")
+        });
+
+        let width = width(size().ok().map(|x| x.0), max, theme.border_style);
+        if width >= MIN_WIDTH {
+            let line: String = text.chars().take(width).collect();
+            let len = line.chars().count();
+            let mut menu = vec![line + &" ".repeat(width - len), " ".repeat(width)];
+
+            if !minimalist {
+                menu.push(components::controls(width, false, theme));
+            }
+
+            draw(menu, width, theme)?;
+        }
+
+        let second = sleep(Duration::from_secs(1));
+        tokio::pin!(second);
+
+        // Every event is matched inside of the branch, since a pattern that doesn't
+        // match would disable the branch until the end of the second.
+        loop {
+            select! {
+                () = &mut second => break,
+                result = &mut signalled => return result.map(|()| false),
+                event = reader.next().fuse() => match event {
+                    Some(Ok(event::Event::Key(event))) if quits(&event, keybinds) => {
+                        return Ok(false);
+                    }
+                    Some(_) => (),
+                    // Without any more input, this can only wait for the second to be over.
+                    None => {
+                        (&mut second).await;
+                        break;
+                    }
+                },
+            }
+        }
+    }
+
+    Ok(true)
+}
+
+/// Gets the widest that the menu can be, from `--width` & `--full-width`.
+pub fn max_width(args: &Args) -> usize {
+    if args.full_width {
+        usize::MAX
+    } else {
+        args.width.map_or(MAX_WIDTH, usize::from)
+    }
+}
+
 /// The code for the terminal interface itself.
 ///
-/// `volume_timer` is a bit strange, but it tracks how long the `volume` bar
-/// has been displayed for, so that it's only displayed for a certain amount of frames.
-async fn interface(player: Arc<Player>, minimalist: bool) -> eyre::Result<()> {
+/// This only redraws when [Player::redraw] is notified or something is animating,
+/// so that lowfi isn't constantly redrawing when nothing has changed. In between,
+/// just the progress bar is redrawn every [TICK] while the track is playing.
+///
+/// `max` is the widest that the menu can be, `deadline` is when the sleep timer
+/// will quit, if it's been set, and `keybinds` are what's listed in the help menu.
+/// Redraws are also never closer together than `frame`, which comes from `--fps`.
+async fn interface(
+    player: Arc<Player>,
+    max: usize,
+    deadline: Option<Instant>,
+    theme: Theme,
+    keybinds: Keybinds,
+    frame: Duration,
+) -> eyre::Result<()> {
+    // The terminal size from the previous frame, which is used to detect resizes.
+    let mut last = size().ok();
+
+    // The track which is being scrolled, and when it started scrolling.
+    let mut scrolled: (Option<Arc<TrackInfo>>, Instant) = (None, Instant::now());
+
+    // The keybindings never change while lowfi is running, so this only has to be built once.
+    let stalled = stalled(&keybinds);
+
+    // When the previous frame was drawn, which is what caps the framerate.
+    let mut drawn: Option<Instant> = None;
+
     loop {
+        // Everything that's animated is based on how much time has passed,
+        // so skipping frames just makes the animations choppier.
+        if let Some(remaining) = drawn.map(|x| frame.saturating_sub(x.elapsed())) {
+            sleep(remaining).await;
+        }
+        drawn = Some(Instant::now());
+
         // Recalculate width each loop in case terminal size changed.
         // Set width to current terminal width, subject to maximum, or fallback
         // to default.
-        let width: usize = match size() {
-          Ok(s) => (s.0 - 4u16).clamp(0, MAX_WIDTH.try_into().unwrap()) as usize,
-          Err(_e) => FALLBACK_WIDTH,
-        };
-        let action = components::action(&player, width);
+        let current = size().ok();
+        let width = width(current.map(|x| x.0), max, theme.border_style);
 
-        let timer = VOLUME_TIMER.load(Ordering::Relaxed);
-        let volume = player.sink.volume();
-        let percentage = format!("{}%", (volume * 100.0).round().abs());
+        // When the terminal is resized, the previous frame might've been wrapped
+        // onto more lines than it was drawn with, so the whole screen is cleared.
+        if current != last {
+            crossterm::execute!(stdout(), Clear(ClearType::All), MoveTo(0, 0))?;
+            last = current;
+        }
 
-        let middle = match timer {
-            0 => components::progress_bar(&player, width - 16),
-            _ => components::audio_bar(volume, &percentage, width - 17),
-        };
+        // A terminal that's too narrow for the menu just gets the name of the track,
+        // which is cut short so that it never has to wrap.
+        if width < MIN_WIDTH {
+            let columns = current.map_or(0, |x| usize::from(x.0));
+            let name = player
+                .current
+                .load()
+                .as_ref()
+                .map_or_else(|| String::from("loading"), |x| x.name.clone());
+            let line: String = name.chars().take(columns.saturating_sub(1)).collect();
 
-        if timer > 0 && timer <= AUDIO_BAR_DURATION {
-            VOLUME_TIMER.fetch_add(1, Ordering::Relaxed);
-        } else if timer > AUDIO_BAR_DURATION {
-            VOLUME_TIMER.store(0, Ordering::Relaxed);
+            crossterm::execute!(
+                stdout(),
+                Clear(ClearType::FromCursorDown),
+                MoveToColumn(0),
+                Print(line),
+                MoveToColumn(0)
+            )?;
+
+            select! {
+                _ = sleep(TICK) => (),
+                _ = player.redraw.notified() => (),
+            }
+
+            continue;
         }
 
-        let controls = components::controls(width);
+        // Any flashed message is shown next to the track name, and so is
+        // a small countdown once the sleep timer is almost done.
+        let mut suffix = player
+            .flashed()
+            .or_else(|| {
+                player
+                    .stalled
+                    .load(Ordering::Relaxed)
+                    .then_some(stalled.as_str())
+            })
+            .map_or_else(String::new, |x| format!(" ({x})"));
+
+        let remaining = deadline.map(|x| x.saturating_duration_since(Instant::now()));
+        if let Some(remaining) = remaining.filter(|x| *x < Duration::from_secs(60)) {
+            suffix.push_str(&format!(" zzz {}s", remaining.as_secs()));
+        }
 
-        let menu = if minimalist {
+        // The position in the list is left out with `--minimalist`, just like the controls,
+        // and also when it would take up more than half of the line.
+        let minimalist = player.minimalist.load(Ordering::Relaxed);
+        let position = components::position(&player)
+            .filter(|x| !minimalist && suffix.len() + x.len() < width / 2);
+        if let Some(position) = position {
+            suffix.push_str(&format!(" {position}"));
+        }
+
+        // The scroll is reset whenever the track changes.
+        let current = player.current.load_full();
+        if current != scrolled.0 {
+            scrolled = (current, Instant::now());
+        }
+
+        // The artist gets its own line above the title, except with `--minimalist` or in the help menu.
+        let help = player.help.load(Ordering::Relaxed);
+        let artist = (!minimalist && !help)
+            .then(|| components::artist(&player, width, &theme))
+            .flatten();
+
+        let scroll = scrolled.1.elapsed().as_millis() / MARQUEE_STEP.as_millis();
+        let (action, scrolling) = components::action(
+            &player,
+            width.saturating_sub(suffix.len()),
+            scroll as usize,
+            artist.is_some(),
+            &theme,
+        );
+        let action = action + &suffix;
+
+        let changed = VOLUME_CHANGED
+            .lock()
+            .unwrap()
+            .filter(|(x, shown)| x.elapsed() < *shown);
+        let volume = player.volume();
+
+        // Whether the progress bar can tick forward by itself, without redrawing everything else.
+        #[allow(unused_mut)]
+        let mut ticking = changed.is_none() && !help;
+
+        let middle = match changed {
+            None => components::progress_bar(&player, width - 16, &theme),
+            Some(_) => components::audio_bar(volume, player.is_muted(), width - 17, &theme),
+        };
+
+        // The visualizer sits to the left of the progress bar, as long as there's room for both.
+        #[cfg(feature = "visualizer")]
+        let middle = match player
+            .visualizer
+            .as_ref()
+            .filter(|_| changed.is_none() && width >= VISUALIZER_MIN_WIDTH)
+        {
+            Some(visualizer) => {
+                ticking = false;
+                let bands = if player.sink.is_paused() {
+                    vec![0.0; VISUALIZER_BANDS]
+                } else {
+                    visualizer.bands(VISUALIZER_BANDS)
+                };
+
+                components::visualizer(&bands, &theme)
+                    + &components::progress_bar(&player, width - 17 - VISUALIZER_BANDS, &theme)
+            }
+            None => middle,
+        };
+
+        let controls = components::controls(width, player.sink.is_paused(), &theme);
+
+        // The progress bar's line is needed for ticking it forward, which moves down with the artist.
+        let above = u16::from(artist.is_some());
+        let menu = if help {
+            components::help(&keybinds, width, &theme)
+        } else if minimalist {
             vec![action, middle]
         } else {
-            vec![action, middle, controls]
+            artist
+                .into_iter()
+                .chain([action, middle, controls])
+                .collect()
         };
 
-        // Formats the menu properly
-        let menu: Vec<String> = menu
-            .into_iter()
-            .map(|x| format!("│ {} │\r\n", x.reset()).to_string())
-            .collect();
-
-        crossterm::execute!(
-            stdout(),
-            Clear(ClearType::FromCursorDown),
-            MoveToColumn(0),
-            Print(format!("┌{}┐\r\n", "─".repeat(width + 2))),
-            Print(menu.join("")),
-            Print(format!("└{}┘", "─".repeat(width + 2))),
-            MoveToColumn(0),
-            MoveUp(menu.len() as u16 + 1)
-        )?;
-
-        sleep(Duration::from_secs_f32(FRAME_DELTA)).await;
+        draw(menu, width, &theme)?;
+
+        // Nothing needs to be redrawn until either something changes, the audio bar
+        // needs to disappear, or a message or countdown next to the name needs updating.
+        let mut wait = changed.map_or(IDLE, |(x, shown)| shown.saturating_sub(x.elapsed()));
+
+        if !suffix.is_empty() {
+            wait = wait.min(TICK);
+        }
+
+        if scrolling {
+            wait = wait.min(MARQUEE_STEP);
+        }
+
+        // Without a duration, the progress bar bounces instead, which has to be redrawn constantly.
+        let bouncing = player
+            .current
+            .load()
+            .as_ref()
+            .is_some_and(|x| x.duration.is_none());
+        if bouncing && !player.sink.is_paused() {
+            wait = wait.min(components::BOUNCE_STEP);
+        }
+
+        // The visualizer is always moving, so it's redrawn as often as `--fps` allows.
+        #[cfg(feature = "visualizer")]
+        if player.visualizer.is_some() && !player.sink.is_paused() {
+            wait = wait.min(frame);
+        }
+
+        // In the meantime, the progress bar ticks forward right after each second of the track,
+        // so that the time never looks like it's skipped one. Nothing ticks while paused.
+        let started = Instant::now();
+        loop {
+            let remaining = wait.saturating_sub(started.elapsed());
+            let tick = (ticking && player.current_exists() && !player.sink.is_paused())
+                .then(|| {
+                    let position = Duration::from_nanos(player.elapsed().subsec_nanos().into());
+                    TICK.saturating_sub(position) + TICK_SLACK
+                })
+                .filter(|x| *x < remaining);
+
+            let notified = select! {
+                _ = sleep(tick.unwrap_or(remaining)) => false,
+                _ = player.redraw.notified() => true,
+            };
+
+            if notified || tick.is_none() || size().ok() != last {
+                break;
+            }
+
+            let middle = components::progress_bar(&player, width - 16, &theme);
+            let rows = 1 + above + theme.border_style.rows();
+            crossterm::execute!(
+                stdout(),
+                MoveDown(rows),
+                MoveToColumn(0),
+                Print(framed(&middle, &theme)),
+                MoveUp(rows),
+                MoveToColumn(0)
+            )?;
+        }
     }
 }
 
@@ -182,13 +734,78 @@ async fn mpris(
         .unwrap()
 }
 
+/// The exit code after a panic, which is the same as when the main thread panics.
+const PANIC_CODE: i32 = 101;
+
+/// Installs a panic hook which calls `restore` before the usual message is printed, and then quits.
+///
+/// Tokio catches panics in tasks, so without quitting, lowfi would just keep going
+/// after the terminal had already been restored, which would leave it unusable.
+/// The hook is swapped back out in [Environment::cleanup].
+fn panic_hook(restore: impl Fn() + Send + Sync + 'static) {
+    let hook = std::panic::take_hook();
+    std::panic::set_hook(Box::new(move |info| {
+        restore();
+        hook(info);
+        std::process::exit(PANIC_CODE);
+    }));
+}
+
 pub struct Environment {
     enhancement: bool,
     alternate: bool,
+
+    /// Whether the terminal was asked to report focus changes, from `--pause-on-unfocus`.
+    focus: bool,
+
+    /// Whether the terminal is being left alone entirely, from `--headless`.
+    headless: bool,
+
+    /// The IPC socket, which is removed when cleaning up.
+    #[cfg(unix)]
+    socket: Option<PathBuf>,
+
+    /// The player to summarize when cleaning up, which is set with [Environment::attach] and
+    /// then taken once it's been summarized, since cleaning up can happen twice.
+    player: std::sync::Mutex<Option<Arc<Player>>>,
+
+    /// Whether to print the last track & how many were played on stdout, from `--print-on-exit`.
+    summary: bool,
+
+    /// Whether to leave out how long lowfi played for, from `--quiet`.
+    quiet: bool,
 }
 
 impl Environment {
-    pub fn ready(alternate: bool) -> eyre::Result<Self> {
+    /// Sets up the terminal, where `enhance` is whether the keyboard enhancement flags
+    /// should be used at all, even if the terminal claims to support them.
+    ///
+    /// If `headless` is true, then the terminal isn't touched at all, and
+    /// the environment just cleans up the IPC socket & prints the summary.
+    /// `focus` is whether the terminal should report when it gains or loses focus.
+    pub fn ready(
+        alternate: bool,
+        enhance: bool,
+        focus: bool,
+        headless: bool,
+        #[cfg(unix)] socket: Option<PathBuf>,
+        summary: bool,
+        quiet: bool,
+    ) -> eyre::Result<Self> {
+        if headless {
+            return Ok(Self {
+                enhancement: false,
+                alternate: false,
+                focus: false,
+                headless,
+                #[cfg(unix)]
+                socket,
+                player: std::sync::Mutex::new(None),
+                summary,
+                quiet,
+            });
+        }
+
         crossterm::execute!(stdout(), Hide)?;
 
         if alternate {
@@ -196,35 +813,113 @@ impl Environment {
         }
 
         terminal::enable_raw_mode()?;
-        let enhancement = terminal::supports_keyboard_enhancement()?;
+        let enhancement = enhance && terminal::supports_keyboard_enhancement()?;
 
         if enhancement {
             crossterm::execute!(
                 stdout(),
-                PushKeyboardEnhancementFlags(KeyboardEnhancementFlags::DISAMBIGUATE_ESCAPE_CODES)
+                PushKeyboardEnhancementFlags(
+                    KeyboardEnhancementFlags::DISAMBIGUATE_ESCAPE_CODES
+                        | KeyboardEnhancementFlags::REPORT_EVENT_TYPES
+                )
             )?;
         }
 
+        // Terminals which don't support this just ignore it.
+        if focus {
+            crossterm::execute!(stdout(), EnableFocusChange)?;
+        }
+
+        // A panic can happen in any thread, so there's no guarantee that the environment
+        // is ever dropped. The terminal is restored first so that the message is readable.
+        panic_hook(move || Self::restore(alternate, enhancement, focus));
+
         Ok(Self {
             enhancement,
             alternate,
+            focus,
+            headless,
+            #[cfg(unix)]
+            socket,
+            player: std::sync::Mutex::new(None),
+            summary,
+            quiet,
         })
     }
 
-    pub fn cleanup(&self) -> eyre::Result<()> {
-        if self.alternate {
-            crossterm::execute!(stdout(), LeaveAlternateScreen)?;
+    /// Puts the terminal back to normal after a panic, ignoring any errors since it's a best effort.
+    fn restore(alternate: bool, enhancement: bool, focus: bool) {
+        if alternate {
+            let _ = crossterm::execute!(stdout(), LeaveAlternateScreen);
         }
 
-        crossterm::execute!(stdout(), Clear(ClearType::FromCursorDown), Show)?;
+        if focus {
+            let _ = crossterm::execute!(stdout(), DisableFocusChange);
+        }
 
-        if self.enhancement {
-            crossterm::execute!(stdout(), PopKeyboardEnhancementFlags)?;
+        let _ = crossterm::execute!(stdout(), Clear(ClearType::FromCursorDown), Show);
+
+        if enhancement {
+            let _ = crossterm::execute!(stdout(), PopKeyboardEnhancementFlags);
+        }
+
+        let _ = terminal::disable_raw_mode();
+    }
+
+    /// Sets the player which is summarized when cleaning up, once it's been created.
+    pub fn attach(&self, player: Arc<Player>) {
+        *self.player.lock().unwrap() = Some(player);
+    }
+
+    pub fn cleanup(&self) -> eyre::Result<()> {
+        if !self.headless {
+            if self.alternate {
+                crossterm::execute!(stdout(), LeaveAlternateScreen)?;
+            }
+
+            if self.focus {
+                crossterm::execute!(stdout(), DisableFocusChange)?;
+            }
+
+            crossterm::execute!(stdout(), Clear(ClearType::FromCursorDown), Show)?;
+
+            if self.enhancement {
+                crossterm::execute!(stdout(), PopKeyboardEnhancementFlags)?;
+            }
+
+            terminal::disable_raw_mode()?;
+
+            // The terminal is back to normal, so a later panic doesn't need to restore it.
+            // This isn't possible while already panicking, but then the hook has done it anyway.
+            if !std::thread::panicking() {
+                let _ = std::panic::take_hook();
+            }
+        }
+
+        #[cfg(unix)]
+        if let Some(socket) = &self.socket {
+            super::ipc::remove(socket);
         }
 
-        terminal::disable_raw_mode()?;
+        let player = self.player.lock().unwrap().take();
 
-        eprintln!("bye! :)");
+        // This goes to stdout so that it can be used by scripts, unlike everything else.
+        if let Some(player) = player.as_ref().filter(|_| self.summary) {
+            let summary = serde_json::json!({
+                "track": player.last_played().map(|x| x.name.clone()),
+                "played": player.played.load(Ordering::Relaxed),
+            });
+
+            println!("{summary}");
+        }
+
+        match player.filter(|_| !self.quiet) {
+            Some(player) => eprintln!(
+                "bye! :) you listened for {}",
+                format_long_duration(&player.listened())
+            ),
+            None => eprintln!("bye! :)"),
+        }
 
         Ok(())
     }
@@ -238,11 +933,21 @@ impl Drop for Environment {
 }
 
 /// Initializes the UI, this will also start taking input from the user.
+/// With `--headless`, only the things which don't need a terminal are started, like MPRIS.
 ///
-/// `alternate` controls whether to use [EnterAlternateScreen] in order to hide
-/// previous terminal history.
-pub async fn start(player: Arc<Player>, sender: Sender<Messages>, args: Args) -> eyre::Result<()> {
-    let environment = Environment::ready(args.alternate)?;
+/// `environment` is set up beforehand, so that [loading] can be shown while the
+/// player is being created, and `keybinds` & `theme` are what was loaded at startup.
+/// The socket from `--ipc` is created beforehand as well, so that any error can be reported.
+pub async fn start(
+    player: Arc<Player>,
+    sender: Sender<Messages>,
+    args: Args,
+    keybinds: Keybinds,
+    theme: Theme,
+    environment: Environment,
+    #[cfg(unix)] ipc: Option<tokio::net::UnixListener>,
+) -> eyre::Result<()> {
+    environment.attach(Arc::clone(&player));
 
     #[cfg(feature = "mpris")]
     {
@@ -252,12 +957,174 @@ pub async fn start(player: Arc<Player>, sender: Sender<Messages>, args: Args) ->
             .await;
     }
 
-    let interface = task::spawn(interface(Arc::clone(&player), args.minimalist));
+    let deadline = args
+        .sleep
+        .map(|x| Instant::now() + Duration::from_secs(x * 60));
+
+    let interface = (!args.headless).then(|| {
+        task::spawn(interface(
+            Arc::clone(&player),
+            max_width(&args),
+            deadline,
+            theme,
+            keybinds.clone(),
+            Duration::from_secs(1) / u32::from(args.fps),
+        ))
+    });
+
+    let timer = deadline.map(|x| task::spawn(sleep_timer(Arc::clone(&player), sender.clone(), x)));
+    let terminated = task::spawn(terminated(sender.clone()));
+
+    #[cfg(unix)]
+    let ipc = ipc.map(|x| task::spawn(super::ipc::listen(Arc::clone(&player), sender.clone(), x)));
+
+    // There's no input without a terminal, so this just waits to be aborted once the player is done.
+    if args.headless {
+        std::future::pending::<()>().await;
+    }
+
+    input(
+        Arc::clone(&player),
+        sender.clone(),
+        keybinds,
+        args.confirm_quit,
+        args.pause_on_unfocus,
+    )
+    .await?;
+    terminated.abort();
+
+    if let Some(interface) = interface {
+        interface.abort();
+    }
+
+    if let Some(timer) = timer {
+        timer.abort();
+    }
 
-    input(sender.clone()).await?;
-    interface.abort();
+    #[cfg(unix)]
+    if let Some(ipc) = ipc {
+        ipc.abort();
+    }
 
     environment.cleanup()?;
 
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use std::process::Command;
+
+    use crossterm::event::{KeyCode, KeyEvent, KeyEventKind, KeyModifiers};
+
+    use super::{
+        keybinds, panic_hook, quits, stalled, width, BorderStyle, Keybinds, FALLBACK_WIDTH,
+        MAX_WIDTH, PANIC_CODE,
+    };
+
+    /// Set when this test binary is running [panic_in_a_task_quits] as a child process.
+    const PANIC_CHILD: &str = "LOWFI_PANIC_CHILD";
+
+    #[test]
+    fn panic_in_a_task_quits() {
+        if std::env::var_os(PANIC_CHILD).is_some() {
+            panic_hook(|| eprintln!("restored the terminal"));
+
+            let runtime = tokio::runtime::Runtime::new().unwrap();
+            runtime.block_on(async {
+                let _ = tokio::spawn(async { panic!("deliberate panic") }).await;
+            });
+
+            // Getting this far means that the panic was caught, and lowfi would've kept going.
+            std::process::exit(0);
+        }
+
+        let output = Command::new(std::env::current_exe().unwrap())
+            .args(["--exact", "player::ui::tests::panic_in_a_task_quits"])
+            .args(["--nocapture", "--test-threads", "1"])
+            .env(PANIC_CHILD, "1")
+            .output()
+            .unwrap();
+
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        assert_eq!(output.status.code(), Some(PANIC_CODE), "{stderr}");
+
+        let find = |x| {
+            stderr
+                .find(x)
+                .unwrap_or_else(|| panic!("{x} isn't in {stderr}"))
+        };
+        assert!(find("restored the terminal") < find("deliberate panic"));
+    }
+
+    #[test]
+    fn ctrl_c_and_the_quit_key_quit() {
+        let runtime = tokio::runtime::Runtime::new().unwrap();
+        let config = std::env::temp_dir().join("lowfi-tests-without-a-config");
+        let keybinds = runtime.block_on(Keybinds::load(&config, false)).unwrap();
+
+        let key = |code, modifiers| KeyEvent::new(code, modifiers);
+        assert!(quits(&keybinds::CTRL_C, &keybinds));
+        assert!(quits(
+            &key(KeyCode::Char('q'), KeyModifiers::NONE),
+            &keybinds
+        ));
+        assert!(!quits(
+            &key(KeyCode::Char('s'), KeyModifiers::NONE),
+            &keybinds
+        ));
+
+        let mut released = key(KeyCode::Char('q'), KeyModifiers::NONE);
+        released.kind = KeyEventKind::Release;
+        assert!(!quits(&released, &keybinds));
+    }
+
+    #[test]
+    fn stalled_names_the_play_pause_key() {
+        let runtime = tokio::runtime::Runtime::new().unwrap();
+        let config = std::env::temp_dir().join("lowfi-tests-without-a-config");
+        let keybinds = runtime.block_on(Keybinds::load(&config, false)).unwrap();
+
+        assert_eq!(stalled(&keybinds), "audio stopped, press p");
+
+        let config = std::env::temp_dir().join(format!("lowfi-tests-{}", std::process::id()));
+        std::fs::create_dir_all(&config).unwrap();
+        std::fs::write(config.join("keybinds.toml"), "play_pause = \"space\"").unwrap();
+
+        let keybinds = runtime.block_on(Keybinds::load(&config, false));
+        std::fs::remove_dir_all(&config).unwrap();
+        assert_eq!(stalled(&keybinds.unwrap()), "audio stopped, press space");
+    }
+
+    #[test]
+    fn width_leaves_room_for_the_border() {
+        for columns in 0..=20u16 {
+            let expected = usize::from(columns).saturating_sub(4);
+            assert_eq!(
+                width(Some(columns), MAX_WIDTH, BorderStyle::Unicode),
+                expected,
+                "{columns} columns"
+            );
+        }
+    }
+
+    #[test]
+    fn width_without_a_border() {
+        for columns in 0..=20u16 {
+            assert_eq!(
+                width(Some(columns), MAX_WIDTH, BorderStyle::None),
+                usize::from(columns)
+            );
+        }
+    }
+
+    #[test]
+    fn width_is_clamped() {
+        assert_eq!(width(Some(20), 10, BorderStyle::Unicode), 10);
+        assert_eq!(
+            width(Some(u16::MAX), MAX_WIDTH, BorderStyle::Ascii),
+            MAX_WIDTH
+        );
+        assert_eq!(width(None, MAX_WIDTH, BorderStyle::Unicode), FALLBACK_WIDTH);
+    }
+}
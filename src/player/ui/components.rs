@@ -1,9 +1,32 @@
-use std::{sync::Arc, time::Duration};
+use std::{
+    sync::{atomic::Ordering, Arc},
+    time::Duration,
+};
 
+#[cfg(feature = "visualizer")]
 use crossterm::style::Stylize;
 
+use super::{
+    keybinds::Keybinds,
+    theme::{paint, Theme},
+};
 use crate::{player::Player, tracks::TrackInfo};
 
+/// The gap in between the end of a scrolling track name and the start of it looping around.
+const MARQUEE_GAP: &str = "   ";
+
+/// Shown before the name of the track while paused, so that it's obvious at a glance.
+const PAUSED: &str = "⏸ paused";
+
+/// The frames of the spinner which is shown while a track is loading.
+const SPINNER: [char; 4] = ['-', '\\', '|', '/'];
+
+/// How wide the block which bounces along the progress bar is, when the duration isn't known.
+const BOUNCE_SIZE: usize = 4;
+
+/// How long the bouncing block takes to move along by a single character.
+pub const BOUNCE_STEP: Duration = Duration::from_millis(100);
+
 /// Small helper function to format durations.
 pub fn format_duration(duration: &Duration) -> String {
     let seconds = duration.as_secs() % 60;
@@ -12,102 +35,349 @@ pub fn format_duration(duration: &Duration) -> String {
     format!("{:02}:{:02}", minutes, seconds)
 }
 
+/// Formats an amount of bytes, like `1.2 MB`.
+fn format_size(bytes: u64) -> String {
+    if bytes < 1024 * 1024 {
+        format!("{} KB", bytes / 1024)
+    } else {
+        format!("{:.1} MB", bytes as f64 / (1024.0 * 1024.0))
+    }
+}
+
 /// Creates the progress bar, as well as all the padding needed.
-pub fn progress_bar(player: &Player, width: usize) -> String {
-    let mut duration = Duration::new(0, 0);
-    let elapsed = player.sink.get_pos();
+///
+/// If the duration of the track isn't known, then the total will just be `--:--`,
+/// and a small block bounces back and forth instead, since there's nothing to fill.
+pub fn progress_bar(player: &Player, width: usize, theme: &Theme) -> String {
+    let mut duration = None;
+    let mut elapsed = player.elapsed();
 
     let mut filled = 0;
+    let mut bouncing = false;
     if let Some(current) = player.current.load().as_ref() {
         if let Some(x) = current.duration {
-            duration = x;
+            duration = Some(x);
 
-            let elapsed = elapsed.as_secs() as f32 / duration.as_secs() as f32;
-            filled = (elapsed * width as f32).round() as usize;
+            // With `--gap`, the position keeps going through the silence after the track.
+            elapsed = elapsed.min(x);
+
+            let elapsed = elapsed.as_secs_f32() / x.as_secs_f32();
+            filled = ((elapsed * width as f32).round() as usize).min(width);
+        } else {
+            bouncing = true;
         }
     };
 
+    let bar = if bouncing {
+        // This is based on the position in the track, so that it stops while paused.
+        let size = BOUNCE_SIZE.min(width);
+        let steps = width - size;
+        let step = (elapsed.as_millis() / BOUNCE_STEP.as_millis()) as usize % (steps * 2).max(1);
+        let offset = if step > steps { steps * 2 - step } else { step };
+
+        theme.glyphs.empty.to_string().repeat(offset)
+            + &theme.glyphs.bar(size, size, theme.progress)
+            + &theme.glyphs.empty.to_string().repeat(steps - offset)
+    } else {
+        theme.glyphs.bar(filled, width, theme.progress)
+    };
+
     format!(
-        " [{}{}] {}/{} ",
-        "/".repeat(filled),
-        " ".repeat(width.saturating_sub(filled)),
+        " [{bar}] {}/{} ",
         format_duration(&elapsed),
-        format_duration(&duration),
+        duration.map_or_else(|| String::from("--:--"), |x| format_duration(&x)),
     )
 }
 
+/// Formats `volume` as a percentage, which has a single decimal place
+/// if it isn't a whole percent, like `7.5%`.
+///
+/// The volume is never negative, since [Player::set_volume] clamps it.
+pub fn percentage(volume: f32) -> String {
+    let tenths = (volume * 1000.0).round();
+    if tenths % 10.0 == 0.0 {
+        format!("{}%", tenths / 10.0)
+    } else {
+        format!("{:.1}%", tenths / 10.0)
+    }
+}
+
 /// Creates the audio bar, as well as all the padding needed.
-pub fn audio_bar(volume: f32, percentage: &str, width: usize) -> String {
-    let audio = (volume * width as f32).round() as usize;
+///
+/// If `muted` is true, then the bar will be empty and say "muted"
+/// instead of showing the percentage.
+pub fn audio_bar(volume: f32, muted: bool, width: usize, theme: &Theme) -> String {
+    let percentage = if muted {
+        String::from("muted")
+    } else {
+        percentage(volume)
+    };
+
+    // The label usually takes up 4 characters, so anything longer shortens the bar.
+    let width = width.saturating_sub(percentage.len().saturating_sub(4));
+    // The bar is just full when the volume is above 100%, but the percentage keeps going.
+    let audio = if muted {
+        0
+    } else {
+        (volume.min(1.0) * width as f32).round() as usize
+    };
 
     format!(
-        " volume: [{}{}] {}{} ",
-        "/".repeat(audio),
-        " ".repeat(width.saturating_sub(audio)),
+        " volume: [{}] {}{} ",
+        theme.glyphs.bar(audio, width, theme.progress),
         " ".repeat(4usize.saturating_sub(percentage.len())),
         percentage,
     )
 }
 
+/// The characters used by the visualizer, from quietest to loudest.
+#[cfg(feature = "visualizer")]
+const LEVELS: [char; 9] = [' ', '▁', '▂', '▃', '▄', '▅', '▆', '▇', '█'];
+
+/// Creates the visualizer, which has a single character for each band in `bands`.
+#[cfg(feature = "visualizer")]
+pub fn visualizer(bands: &[f32], theme: &Theme) -> String {
+    let bars: String = bands
+        .iter()
+        .map(|x| LEVELS[((x * (LEVELS.len() - 1) as f32).round() as usize).min(LEVELS.len() - 1)])
+        .collect();
+
+    format!(" {}", paint(bars.stylize(), theme.progress))
+}
+
 /// This represents the main "action" bars state.
 enum ActionBar {
     Paused(TrackInfo),
     Playing(TrackInfo),
-    Loading,
+
+    /// Waiting for the next track, with the current frame of the spinner,
+    /// alongside the progress of the download from [Player::progress].
+    Loading(char, Option<(u64, Option<u64>)>),
+
+    /// Still loading, but the download failed and is being retried.
+    Retrying(char),
 }
 
 impl ActionBar {
-    /// Formats the action bar to be displayed.
+    /// Formats the action bar to be displayed, fitting it into `width`.
     /// The second value is the character length of the result.
-    fn format(&self) -> (String, usize) {
+    ///
+    /// If the name is too long, then it'll be scrolled by `scroll` characters,
+    /// and the third value will be true. This is also true while the spinner is animating.
+    fn format(&self, width: usize, scroll: usize, theme: &Theme) -> (String, usize, bool) {
         let (word, subject) = match self {
-            Self::Playing(x) => ("playing", Some(x.name.clone())),
-            Self::Paused(x) => ("paused", Some(x.name.clone())),
-            Self::Loading => ("loading", None),
+            Self::Playing(x) => ("playing", x.name.clone()),
+            Self::Paused(x) => (PAUSED, x.name.clone()),
+            Self::Loading(frame, _) | Self::Retrying(frame) => {
+                let word = if let Self::Retrying(_) = self {
+                    "retrying"
+                } else {
+                    "buffering"
+                };
+
+                let bar = format!("{word} {frame}");
+                let detail = match self {
+                    Self::Loading(_, Some((downloaded, Some(length)))) => Some(format!(
+                        "{bar} {}, {}%",
+                        format_size(*downloaded),
+                        downloaded * 100 / length
+                    )),
+                    Self::Loading(_, Some((downloaded, None))) => {
+                        Some(format!("{bar} {}", format_size(*downloaded)))
+                    }
+                    _ => None,
+                };
+
+                // The details of the download are left out if they don't fit.
+                let bar = detail.filter(|x| x.len() <= width).unwrap_or(bar);
+                let len = bar.len();
+                return (bar, len, true);
+            }
         };
 
-        subject.map_or_else(
-            || (word.to_owned(), word.len()),
-            |subject| {
-                (
-                    format!("{} {}", word, subject.clone().bold()),
-                    word.len() + 1 + subject.len(),
-                )
-            },
+        // The paused marker isn't ASCII, so the length has to be counted in characters.
+        let prefix = word.chars().count() + 1;
+        let available = width.saturating_sub(prefix);
+        let len = subject.chars().count();
+        if len <= available {
+            return (
+                format!("{} {}", word, paint(theme.bold(subject), theme.name)),
+                prefix + len,
+                false,
+            );
+        }
+
+        let looped: Vec<char> = subject.chars().chain(MARQUEE_GAP.chars()).collect();
+        let window: String = looped
+            .iter()
+            .cycle()
+            .skip(scroll % looped.len())
+            .take(available)
+            .collect();
+
+        (
+            format!("{} {}", word, paint(theme.bold(window), theme.name)),
+            prefix + available,
+            true,
         )
     }
 }
 
 /// Creates the top/action bar, which has the name of the track and it's status.
-/// This also creates all the needed padding.
-pub fn action(player: &Player, width: usize) -> String {
-    let (main, len) = player
+/// This also creates all the needed padding, and shows `[R]` when repeating.
+///
+/// Names that don't fit will scroll by `scroll` characters, which also
+/// animates the spinner while loading, and the second value is whether either is happening.
+/// If `split` is true, then only the title is shown, since the artist is on the line above.
+pub fn action(
+    player: &Player,
+    width: usize,
+    scroll: usize,
+    split: bool,
+    theme: &Theme,
+) -> (String, bool) {
+    let repeat = if player.repeat.load(Ordering::Relaxed) {
+        " [R]"
+    } else {
+        ""
+    };
+    let width = width.saturating_sub(repeat.len());
+
+    let frame = SPINNER[scroll % SPINNER.len()];
+    let loading = if player.list.is_retrying() {
+        ActionBar::Retrying(frame)
+    } else {
+        ActionBar::Loading(frame, player.progress())
+    };
+
+    let (main, len, scrolling) = player
         .current
         .load()
         .as_ref()
-        .map_or(ActionBar::Loading, |x| {
-            let name = (*Arc::clone(x)).clone();
+        .filter(|_| !player.loading.load(Ordering::Relaxed))
+        .map_or(loading, |x| {
+            let mut name = (*Arc::clone(x)).clone();
+            if let Some((_, title)) = x.split_artist().filter(|_| split) {
+                name.name = title.to_owned();
+            }
+
             if player.sink.is_paused() {
                 ActionBar::Paused(name)
             } else {
                 ActionBar::Playing(name)
             }
         })
-        .format();
+        .format(width, scroll, theme);
 
-    if len > width {
-        format!("{}...", &main[..=width])
-    } else {
-        format!("{}{}", main, " ".repeat(width - len))
+    let bar = format!("{}{}", main, " ".repeat(width.saturating_sub(len)));
+    (bar + repeat, scrolling)
+}
+
+/// Creates the line above the action bar which has the artist of the current track,
+/// if its name looks like "Artist - Title", padded to `width`.
+pub fn artist(player: &Player, width: usize, theme: &Theme) -> Option<String> {
+    if player.loading.load(Ordering::Relaxed) {
+        return None;
     }
+
+    let current = player.current.load();
+    let (artist, _) = current.as_ref()?.split_artist()?;
+
+    let artist: String = artist.chars().take(width).collect();
+    let len = artist.chars().count();
+    Some(format!(
+        "{}{}",
+        theme.dim(artist),
+        " ".repeat(width.saturating_sub(len))
+    ))
+}
+
+/// Shows where the current track is in the list, like `track 12/340`,
+/// or just how many tracks there are while shuffling.
+pub fn position(player: &Player) -> Option<String> {
+    let (index, len) = player.position()?;
+    Some(if player.list.is_shuffled() {
+        format!("{len} tracks")
+    } else {
+        format!("track {}/{len}", index + 1)
+    })
+}
+
+/// Creates the top bar while the player is still being set up, padded to `width`,
+/// where `scroll` animates the spinner just like it does while buffering.
+pub fn loading(width: usize, scroll: usize) -> String {
+    let bar = format!("loading {}", SPINNER[scroll % SPINNER.len()]);
+    let len = bar.len();
+    bar + &" ".repeat(width.saturating_sub(len))
 }
 
 /// Creates the bottom controls bar, and also spaces it properly.
-pub fn controls(width: usize) -> String {
-    let controls = [["[s]", "kip"], ["[p]", "ause"], ["[q]", "uit"]];
+///
+/// `paused` is whether `p` will resume playback, instead of pausing it.
+pub fn controls(width: usize, paused: bool, theme: &Theme) -> String {
+    let pause = if paused {
+        ["[p]", "lay"]
+    } else {
+        ["[p]", "ause"]
+    };
+
+    let controls = [["[s]", "kip"], pause, ["[q]", "uit"]];
     let len: usize = controls.concat().iter().map(|x| x.len()).sum();
-    let controls = controls.map(|x| format!("{}{}", x[0].bold(), x[1]));
-    let offset = if width % 2 == 0 {" "} else {""};
-    controls.join(&" ".repeat((width - len) / (controls.len() - 1))) + offset
+    let controls = controls.map(|x| format!("{}{}", theme.bold(x[0]), x[1]));
+
+    let gap = width.saturating_sub(len) / (controls.len() - 1);
+    let offset = width.saturating_sub(len + gap * (controls.len() - 1));
+    controls.join(&" ".repeat(gap)) + &" ".repeat(offset)
+}
+
+/// Creates the help menu, which lists every action alongside its keys.
+///
+/// Each line is cut off if it's too long for `width`.
+pub fn help(keybinds: &Keybinds, width: usize, theme: &Theme) -> Vec<String> {
+    let title = "keybindings";
+    let mut lines = vec![format!(
+        "{}{}",
+        theme.bold(title),
+        " ".repeat(width.saturating_sub(title.len()))
+    )];
+
+    for (action, keys) in keybinds.names() {
+        let line = format!("{:<17}{}", action.replace('_', " "), keys.join(", "));
+        let line: String = line.chars().take(width).collect();
+        let len = line.chars().count();
+
+        lines.push(line + &" ".repeat(width.saturating_sub(len)));
+    }
+
+    lines
 }
 
+#[cfg(test)]
+mod tests {
+    use super::percentage;
+
+    #[test]
+    fn whole_percentages() {
+        assert_eq!(percentage(0.0), "0%");
+        assert_eq!(percentage(0.5), "50%");
+        assert_eq!(percentage(1.0), "100%");
+        assert_eq!(percentage(2.5), "250%");
+    }
+
+    #[test]
+    fn fractional_percentages() {
+        assert_eq!(percentage(0.075), "7.5%");
+        assert_eq!(percentage(0.005), "0.5%");
+        assert_eq!(percentage(1.255), "125.5%");
+    }
+
+    #[test]
+    fn nudged_percentages() {
+        let nudged = (0..7).fold(0.0f32, |x, _| x + 0.01);
+        assert_eq!(percentage(nudged), "7%");
+
+        // Anything smaller than a tenth of a percent is rounded away.
+        assert_eq!(percentage(0.0004), "0%");
+        assert_eq!(percentage(0.9999), "100%");
+    }
+}
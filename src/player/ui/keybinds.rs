@@ -0,0 +1,311 @@
+//! Responsible for mapping keys to [Messages], including
+//! loading custom bindings from `keybinds.toml`.
+
+use std::{collections::HashMap, path::Path};
+
+use crossterm::event::{KeyCode, KeyEvent, KeyModifiers, MediaKeyCode};
+use eyre::{bail, eyre};
+use tokio::fs;
+
+use crate::player::Messages;
+
+/// How many seconds the seek keys will skip by.
+const SEEK: i64 = 5;
+
+/// All of the actions which can be bound, alongside their [Messages].
+const ACTIONS: &[(&str, Messages)] = &[
+    ("next", Messages::Next),
+    ("previous", Messages::Previous),
+    ("play_pause", Messages::PlayPause),
+    ("play", Messages::Play),
+    ("pause", Messages::Pause),
+    ("quit", Messages::Quit),
+    ("volume_up", Messages::ChangeVolume(0.1)),
+    ("volume_down", Messages::ChangeVolume(-0.1)),
+    ("volume_up_fine", Messages::ChangeVolume(0.01)),
+    ("volume_down_fine", Messages::ChangeVolume(-0.01)),
+    ("volume_0", Messages::SetVolume(0.0)),
+    ("volume_10", Messages::SetVolume(0.1)),
+    ("volume_20", Messages::SetVolume(0.2)),
+    ("volume_30", Messages::SetVolume(0.3)),
+    ("volume_40", Messages::SetVolume(0.4)),
+    ("volume_50", Messages::SetVolume(0.5)),
+    ("volume_60", Messages::SetVolume(0.6)),
+    ("volume_70", Messages::SetVolume(0.7)),
+    ("volume_80", Messages::SetVolume(0.8)),
+    ("volume_90", Messages::SetVolume(0.9)),
+    ("volume_100", Messages::SetVolume(1.0)),
+    ("toggle_mute", Messages::ToggleMute),
+    ("bookmark", Messages::Bookmark),
+    ("remember_volume", Messages::RememberVolume),
+    ("block", Messages::Block),
+    ("repeat", Messages::ToggleRepeat),
+    ("shuffle", Messages::ToggleShuffle),
+    ("seek_backward", Messages::Seek(-SEEK)),
+    ("seek_forward", Messages::Seek(SEEK)),
+    ("restart", Messages::Restart),
+    ("help", Messages::ToggleHelp),
+    ("controls", Messages::ToggleControls),
+];
+
+/// The default keys for each action, used whenever
+/// an action isn't specified in `keybinds.toml`.
+const DEFAULTS: &[(&str, &[&str])] = &[
+    ("next", &["s", "n", "tracknext"]),
+    ("previous", &["z", "trackprevious"]),
+    ("play_pause", &["p", "playpause"]),
+    ("play", &["play"]),
+    ("pause", &["pause", "stop"]),
+    ("quit", &["q"]),
+    ("volume_up", &["up", "+", "=", "raisevolume"]),
+    ("volume_down", &["down", "-", "_", "lowervolume"]),
+    ("volume_up_fine", &["right"]),
+    ("volume_down_fine", &["left"]),
+    ("volume_0", &["0"]),
+    ("volume_10", &["1"]),
+    ("volume_20", &["2"]),
+    ("volume_30", &["3"]),
+    ("volume_40", &["4"]),
+    ("volume_50", &["5"]),
+    ("volume_60", &["6"]),
+    ("volume_70", &["7"]),
+    ("volume_80", &["8"]),
+    ("volume_90", &["9"]),
+    ("volume_100", &[")"]),
+    ("toggle_mute", &["m", "mutevolume"]),
+    ("bookmark", &["b"]),
+    ("remember_volume", &["v"]),
+    ("block", &["x"]),
+    ("repeat", &["r"]),
+    ("shuffle", &["o"]),
+    ("seek_backward", &["[", "shift+left"]),
+    ("seek_forward", &["]", "shift+right"]),
+    ("restart", &["home", "backspace"]),
+    ("help", &["?"]),
+    ("controls", &["i", "tab"]),
+];
+
+/// Old names for actions, which still work in `keybinds.toml`.
+///
+/// `mute` used to just set the volume to zero, but now it's the same as `toggle_mute`,
+/// so that muting twice brings the volume back.
+const ALIASES: &[(&str, &str)] = &[("mute", "toggle_mute")];
+
+/// The extra keys which are added with `--vim-keys`.
+const VIM: &[(&str, &[&str])] = &[
+    ("volume_up", &["k"]),
+    ("volume_down", &["j"]),
+    ("volume_up_fine", &["l"]),
+    ("volume_down_fine", &["h"]),
+];
+
+/// Ctrl+C, which is always bound to [Messages::Quit] so that
+/// users can't trap themselves inside of lowfi.
+pub const CTRL_C: KeyEvent = KeyEvent::new(KeyCode::Char('c'), KeyModifiers::CONTROL);
+
+/// Strips the parts of a [KeyEvent] which shouldn't matter when matching it,
+/// like the case of the character and whether shift was held.
+fn normalize(code: KeyCode, modifiers: KeyModifiers) -> KeyEvent {
+    match code {
+        KeyCode::Char(character) => KeyEvent::new(
+            KeyCode::Char(character.to_ascii_lowercase()),
+            modifiers.difference(KeyModifiers::SHIFT),
+        ),
+        _ => KeyEvent::new(code, modifiers),
+    }
+}
+
+/// Parses a key like `ctrl+n`, `up` or `+` into a [KeyEvent].
+fn parse_key(key: &str) -> eyre::Result<KeyEvent> {
+    let lowercase = key.to_lowercase();
+
+    // The key itself can also be a `+`, so this is a bit more involved than a simple split.
+    let (modifiers, code) = if lowercase == "+" {
+        ("", "+")
+    } else if let Some(modifiers) = lowercase.strip_suffix("++") {
+        (modifiers, "+")
+    } else {
+        lowercase.rsplit_once('+').unwrap_or(("", &lowercase))
+    };
+
+    let mut parsed = KeyModifiers::NONE;
+    for modifier in modifiers.split('+').filter(|x| !x.is_empty()) {
+        parsed |= match modifier {
+            "ctrl" | "control" => KeyModifiers::CONTROL,
+            "alt" => KeyModifiers::ALT,
+            "shift" => KeyModifiers::SHIFT,
+            _ => bail!("unknown modifier `{modifier}` in key `{key}`"),
+        };
+    }
+
+    let code = match code {
+        "up" => KeyCode::Up,
+        "down" => KeyCode::Down,
+        "left" => KeyCode::Left,
+        "right" => KeyCode::Right,
+        "space" => KeyCode::Char(' '),
+        "enter" => KeyCode::Enter,
+        "tab" => KeyCode::Tab,
+        "backspace" => KeyCode::Backspace,
+        "esc" => KeyCode::Esc,
+        "home" => KeyCode::Home,
+        "end" => KeyCode::End,
+        "pageup" => KeyCode::PageUp,
+        "pagedown" => KeyCode::PageDown,
+        "play" => KeyCode::Media(MediaKeyCode::Play),
+        "pause" => KeyCode::Media(MediaKeyCode::Pause),
+        "playpause" => KeyCode::Media(MediaKeyCode::PlayPause),
+        "stop" => KeyCode::Media(MediaKeyCode::Stop),
+        "tracknext" => KeyCode::Media(MediaKeyCode::TrackNext),
+        "trackprevious" => KeyCode::Media(MediaKeyCode::TrackPrevious),
+        "lowervolume" => KeyCode::Media(MediaKeyCode::LowerVolume),
+        "raisevolume" => KeyCode::Media(MediaKeyCode::RaiseVolume),
+        "mutevolume" => KeyCode::Media(MediaKeyCode::MuteVolume),
+        function if function.len() > 1 && function.starts_with('f') => function[1..]
+            .parse()
+            .map(KeyCode::F)
+            .map_err(|_| eyre!("unknown key `{key}`"))?,
+        character => {
+            let mut chars = character.chars();
+            match (chars.next(), chars.next()) {
+                (Some(character), None) => KeyCode::Char(character),
+                _ => bail!("unknown key `{key}`"),
+            }
+        }
+    };
+
+    Ok(normalize(code, parsed))
+}
+
+/// The keybindings, which map each key to the [Messages] that it sends.
+#[derive(Debug, Clone)]
+pub struct Keybinds {
+    map: HashMap<KeyEvent, Messages>,
+
+    /// Every action alongside the keys bound to it, as they were written,
+    /// which is used to show the keybindings in the help menu.
+    names: Vec<(&'static str, Vec<String>)>,
+}
+
+impl Keybinds {
+    /// Builds the bindings from a list of actions and the keys for each.
+    ///
+    /// If `strict` is true, then a key which is bound twice will be an error,
+    /// otherwise the first binding takes priority.
+    fn build<'a>(
+        bindings: impl IntoIterator<Item = (&'a str, Vec<String>, bool)>,
+    ) -> eyre::Result<Self> {
+        let mut map = HashMap::from([(CTRL_C, Messages::Quit)]);
+        let mut names: HashMap<KeyEvent, &str> = HashMap::from([(CTRL_C, "quit")]);
+        let mut bound: HashMap<&str, Vec<String>> = HashMap::new();
+
+        for (action, keys, strict) in bindings {
+            let message = ACTIONS
+                .iter()
+                .find(|x| x.0 == action)
+                .ok_or(eyre!("unknown action `{action}` in keybinds.toml"))?
+                .1;
+
+            for key in keys {
+                let event = parse_key(&key)?;
+
+                if let Some(existing) = names.get(&event) {
+                    if event == CTRL_C && message != Messages::Quit {
+                        bail!("ctrl+c can't be rebound, since it's always used to quit");
+                    }
+
+                    if strict && *existing != action {
+                        bail!("`{key}` is bound to both `{existing}` and `{action}`");
+                    }
+
+                    continue;
+                }
+
+                names.insert(event, action);
+                map.insert(event, message);
+                bound.entry(action).or_default().push(key.to_lowercase());
+            }
+        }
+
+        bound
+            .entry("quit")
+            .or_default()
+            .push(String::from("ctrl+c"));
+
+        let names = ACTIONS
+            .iter()
+            .filter_map(|(action, _)| Some((*action, bound.remove(action)?)))
+            .collect();
+
+        Ok(Self { map, names })
+    }
+
+    /// Loads the keybindings from `keybinds.toml` in the config directory.
+    ///
+    /// Every action which isn't specified in the file will keep its default keys,
+    /// unless those keys were already taken by another action in the file.
+    /// `vim` will also add the `hjkl` volume controls on top of everything else.
+    pub async fn load(config: &Path, vim: bool) -> eyre::Result<Self> {
+        let path = config.join("keybinds.toml");
+        let table: toml::Table = if path.exists() {
+            fs::read_to_string(path)
+                .await?
+                .parse()
+                .map_err(|error| eyre!("keybinds.toml is invalid: {error}"))?
+        } else {
+            toml::Table::new()
+        };
+
+        let table: toml::Table = table
+            .into_iter()
+            .map(
+                |(action, keys)| match ALIASES.iter().find(|x| x.0 == action) {
+                    Some((_, action)) => (action.to_string(), keys),
+                    None => (action, keys),
+                },
+            )
+            .collect();
+
+        let mut custom = Vec::new();
+        for (action, keys) in &table {
+            let keys = match keys {
+                toml::Value::String(key) => vec![key.clone()],
+                toml::Value::Array(keys) => keys
+                    .iter()
+                    .map(|x| {
+                        x.as_str().map(String::from).ok_or(eyre!(
+                            "keys for `{action}` in keybinds.toml must be strings"
+                        ))
+                    })
+                    .collect::<eyre::Result<_>>()?,
+                _ => bail!("keys for `{action}` in keybinds.toml must be a string or a list"),
+            };
+
+            custom.push((action.as_str(), keys, true));
+        }
+
+        let defaults = DEFAULTS
+            .iter()
+            .filter(|(action, _)| !table.contains_key(*action))
+            .chain(VIM.iter().filter(|_| vim))
+            .map(|(action, keys)| (*action, keys.iter().map(|x| x.to_string()).collect(), false));
+
+        Self::build(custom.into_iter().chain(defaults))
+    }
+
+    /// Gets the [Messages] which should be sent for a key, if any.
+    ///
+    /// This will first try to match the modifiers exactly, and then fall back
+    /// to the key alone, so that something like `ctrl+s` will still skip.
+    pub fn get(&self, code: KeyCode, modifiers: KeyModifiers) -> Option<Messages> {
+        self.map
+            .get(&normalize(code, modifiers))
+            .or_else(|| self.map.get(&normalize(code, KeyModifiers::NONE)))
+            .copied()
+    }
+
+    /// Every action which has keys bound to it, in the same order as the defaults.
+    pub fn names(&self) -> &[(&'static str, Vec<String>)] {
+        &self.names
+    }
+}
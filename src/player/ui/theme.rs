@@ -0,0 +1,265 @@
+//! Responsible for loading the colors of the interface from `theme.toml`.
+
+use std::{env, fmt::Display, path::Path};
+
+use crossterm::style::{Color, ContentStyle, StyledContent, Stylize};
+use eyre::{bail, eyre};
+use tokio::fs;
+use unicode_width::UnicodeWidthChar;
+
+/// All of the keys which can be set in `theme.toml`.
+const KEYS: &[&str] = &["border", "progress", "name", "filled", "empty", "head"];
+
+/// Colors `content` with `color`, or leaves it alone if there isn't one.
+pub fn paint<D: Display>(content: StyledContent<D>, color: Option<Color>) -> StyledContent<D> {
+    match color {
+        Some(color) => content.with(color),
+        None => content,
+    }
+}
+
+/// Whether the terminal should be given colors & styles like bold at all.
+///
+/// This is false if `NO_COLOR` is set to anything, as per <https://no-color.org>,
+/// or if the terminal is `dumb`, which usually means it can't handle escape codes.
+pub fn supports_color() -> bool {
+    let disabled = env::var_os("NO_COLOR").is_some_and(|x| !x.is_empty());
+    let dumb = env::var_os("TERM").is_some_and(|x| x == "dumb");
+
+    !disabled && !dumb
+}
+
+/// Parses either a named color like `dark_cyan`, or a hex color like `#ff8800`.
+fn parse_color(value: &str) -> Option<Color> {
+    let Some(hex) = value.strip_prefix('#') else {
+        return Color::try_from(value).ok();
+    };
+
+    if hex.len() != 6 {
+        return None;
+    }
+
+    let channel = |i: usize| u8::from_str_radix(hex.get(i..i + 2)?, 16).ok();
+    Some(Color::Rgb {
+        r: channel(0)?,
+        g: channel(2)?,
+        b: channel(4)?,
+    })
+}
+
+/// The characters that the box around the interface is drawn with, from `--border`.
+#[derive(clap::ValueEnum, Debug, Clone, Copy, PartialEq, Default)]
+pub enum BorderStyle {
+    /// Square corners, like `┌─┐`.
+    #[default]
+    Unicode,
+
+    /// Rounded corners, like `╭─╮`.
+    Rounded,
+
+    /// Plain ASCII, like `+-+`, for terminals or fonts without box-drawing characters.
+    Ascii,
+
+    /// No box at all.
+    None,
+}
+
+impl BorderStyle {
+    /// The top left, top right, bottom left & bottom right corners,
+    /// followed by the horizontal & vertical lines.
+    pub fn glyphs(self) -> Option<[char; 6]> {
+        match self {
+            Self::Unicode => Some(['┌', '┐', '└', '┘', '─', '│']),
+            Self::Rounded => Some(['╭', '╮', '╰', '╯', '─', '│']),
+            Self::Ascii => Some(['+', '+', '+', '+', '-', '|']),
+            Self::None => None,
+        }
+    }
+
+    /// How many columns the border & its padding take up, which is both sides together.
+    pub fn columns(self) -> usize {
+        match self {
+            Self::None => 0,
+            _ => 4,
+        }
+    }
+
+    /// How many lines the border takes up above the menu.
+    pub fn rows(self) -> u16 {
+        match self {
+            Self::None => 0,
+            _ => 1,
+        }
+    }
+}
+
+/// The characters which the progress & audio bars are drawn with.
+#[derive(Debug, Clone)]
+pub struct Glyphs {
+    /// The part of the bar which has been filled in.
+    pub filled: char,
+
+    /// The rest of the bar.
+    pub empty: char,
+
+    /// Drawn at the end of the filled part, if it's set.
+    pub head: Option<char>,
+}
+
+impl Default for Glyphs {
+    fn default() -> Self {
+        Self {
+            filled: '/',
+            empty: ' ',
+            head: None,
+        }
+    }
+}
+
+impl Glyphs {
+    /// Draws a bar which is `width` characters long, with `filled` of them filled in.
+    pub fn bar(&self, filled: usize, width: usize, color: Option<Color>) -> String {
+        let filled = filled.min(width);
+        let mut bar = self.filled.to_string().repeat(filled);
+
+        // The head replaces the last filled character, so that the bar stays the same width.
+        if let Some(head) = self.head.filter(|_| filled > 0 && filled < width) {
+            bar.pop();
+            bar.push(head);
+        }
+
+        let empty = self.empty.to_string().repeat(width - filled);
+        format!("{}{}", paint(bar.stylize(), color), empty)
+    }
+}
+
+/// Parses a glyph, which has to be exactly one character & take up one column,
+/// since anything else would break the alignment of the box.
+fn parse_glyph(value: &str) -> Option<char> {
+    let mut chars = value.chars();
+    let glyph = chars.next()?;
+
+    if chars.next().is_some() || glyph.width() != Some(1) {
+        return None;
+    }
+
+    Some(glyph)
+}
+
+/// The colors & glyphs of the interface, where [None] just uses the terminal's default.
+#[derive(Debug, Clone, Default)]
+pub struct Theme {
+    /// The box drawn around everything.
+    pub border: Option<Color>,
+
+    /// The filled part of the progress & audio bars.
+    pub progress: Option<Color>,
+
+    /// The name of the current track.
+    pub name: Option<Color>,
+
+    /// The characters used for the progress & audio bars.
+    pub glyphs: Glyphs,
+
+    /// How the box around everything is drawn, which comes from `--border` rather than `theme.toml`.
+    pub border_style: BorderStyle,
+
+    /// Whether everything is drawn as plain text, without any colors or bold, see [supports_color].
+    pub plain: bool,
+}
+
+impl Theme {
+    /// Loads the theme from `theme.toml` in the config directory.
+    ///
+    /// If the file doesn't exist, then everything is left uncolored.
+    pub async fn load(config: &Path) -> eyre::Result<Self> {
+        let path = config.join("theme.toml");
+        if !path.exists() {
+            return Ok(Self::default());
+        }
+
+        let table: toml::Table = fs::read_to_string(path)
+            .await?
+            .parse()
+            .map_err(|error| eyre!("theme.toml is invalid: {error}"))?;
+
+        if let Some(key) = table.keys().find(|x| !KEYS.contains(&x.as_str())) {
+            bail!("unknown key `{key}` in theme.toml");
+        }
+
+        let string = |key: &str| -> eyre::Result<Option<&str>> {
+            table
+                .get(key)
+                .map(|x| {
+                    x.as_str()
+                        .ok_or(eyre!("`{key}` in theme.toml must be a string"))
+                })
+                .transpose()
+        };
+
+        let color = |key: &str| -> eyre::Result<Option<Color>> {
+            let Some(value) = string(key)? else {
+                return Ok(None);
+            };
+
+            parse_color(value).map(Some).ok_or(eyre!(
+                "`{value}` isn't a valid color for `{key}` in theme.toml"
+            ))
+        };
+
+        let glyph = |key: &str| -> eyre::Result<Option<char>> {
+            let Some(value) = string(key)? else {
+                return Ok(None);
+            };
+
+            parse_glyph(value).map(Some).ok_or(eyre!(
+                "`{value}` for `{key}` in theme.toml must be a single character, one column wide"
+            ))
+        };
+
+        let defaults = Glyphs::default();
+        Ok(Self {
+            border: color("border")?,
+            progress: color("progress")?,
+            name: color("name")?,
+            glyphs: Glyphs {
+                filled: glyph("filled")?.unwrap_or(defaults.filled),
+                empty: glyph("empty")?.unwrap_or(defaults.empty),
+                head: glyph("head")?,
+            },
+            border_style: BorderStyle::default(),
+            plain: false,
+        })
+    }
+
+    /// Removes all of the colors, and makes sure that nothing else is styled either.
+    pub fn without_color(self) -> Self {
+        Self {
+            border: None,
+            progress: None,
+            name: None,
+            plain: true,
+            ..self
+        }
+    }
+
+    /// Makes `content` dimmer than the text around it, unless the theme is [plain](Theme::plain).
+    pub fn dim<D: Display>(&self, content: D) -> StyledContent<D> {
+        let content = StyledContent::new(ContentStyle::new(), content);
+        if self.plain {
+            content
+        } else {
+            content.dim()
+        }
+    }
+
+    /// Makes `content` bold, unless the theme is [plain](Theme::plain).
+    pub fn bold<D: Display>(&self, content: D) -> StyledContent<D> {
+        let content = StyledContent::new(ContentStyle::new(), content);
+        if self.plain {
+            content
+        } else {
+            content.bold()
+        }
+    }
+}
@@ -0,0 +1,197 @@
+//! Contains the [Visualizer], which splits whatever's playing into a few frequency bands.
+
+use std::{
+    collections::VecDeque,
+    sync::{
+        atomic::{AtomicU32, Ordering},
+        Arc, Mutex,
+    },
+    time::Duration,
+};
+
+use rodio::{cpal::FromSample, source::SeekError, Sample, Source};
+use rustfft::{num_complex::Complex, Fft, FftPlanner};
+
+/// How many samples are analyzed at once, which has to be a power of two.
+const SIZE: usize = 1024;
+
+/// How many samples are collected by [Tap] before they're handed to the [Visualizer].
+const BATCH: usize = 256;
+
+/// The lowest frequency which is shown, in hertz.
+const MIN_FREQUENCY: f32 = 50.0;
+
+/// The highest frequency which is shown, in hertz.
+const MAX_FREQUENCY: f32 = 12000.0;
+
+/// How many decibels below full scale count as silence.
+const RANGE: f32 = 60.0;
+
+/// Keeps the most recently played samples, which are analyzed whenever the UI is drawn.
+pub struct Visualizer {
+    /// The last [SIZE] samples, mixed down into a single channel.
+    samples: Mutex<VecDeque<f32>>,
+
+    /// The sample rate of the track which is being played.
+    rate: AtomicU32,
+
+    fft: Arc<dyn Fft<f32>>,
+}
+
+impl Visualizer {
+    pub fn new() -> Self {
+        Self {
+            samples: Mutex::new(VecDeque::with_capacity(SIZE)),
+            rate: AtomicU32::new(44100),
+            fft: FftPlanner::new().plan_fft_forward(SIZE),
+        }
+    }
+
+    /// Adds `samples` to the end of the buffer, dropping the oldest ones.
+    ///
+    /// This gives up if the buffer is being read, since it's called
+    /// from the audio thread which should never have to wait.
+    fn push(&self, samples: &mut Vec<f32>) {
+        let Ok(mut buffer) = self.samples.try_lock() else {
+            // Only the most recent samples matter, so the rest can be thrown away.
+            let excess = samples.len().saturating_sub(SIZE);
+            samples.drain(..excess);
+            return;
+        };
+
+        buffer.extend(samples.drain(..));
+        let excess = buffer.len().saturating_sub(SIZE);
+        buffer.drain(..excess);
+    }
+
+    /// Works out how loud each of `count` bands are, from 0.0 to 1.0.
+    ///
+    /// The bands are spaced logarithmically, which is closer to how pitch is heard.
+    pub fn bands(&self, count: usize) -> Vec<f32> {
+        let mut buffer: Vec<Complex<f32>> = {
+            let samples = self.samples.lock().unwrap();
+            if samples.len() < SIZE {
+                return vec![0.0; count];
+            }
+
+            // A Hann window, which stops the edges of the buffer from smearing across every band.
+            samples
+                .iter()
+                .enumerate()
+                .map(|(i, x)| {
+                    let window = 0.5
+                        - 0.5 * (2.0 * std::f32::consts::PI * i as f32 / (SIZE - 1) as f32).cos();
+                    Complex::new(x * window, 0.0)
+                })
+                .collect()
+        };
+
+        self.fft.process(&mut buffer);
+
+        let resolution = self.rate.load(Ordering::Relaxed) as f32 / SIZE as f32;
+        let edge = |band: usize| {
+            let frequency =
+                MIN_FREQUENCY * (MAX_FREQUENCY / MIN_FREQUENCY).powf(band as f32 / count as f32);
+            ((frequency / resolution) as usize).clamp(1, SIZE / 2)
+        };
+
+        (0..count)
+            .map(|band| {
+                let start = edge(band);
+                let end = edge(band + 1).max(start + 1).min(SIZE / 2);
+
+                let peak = buffer[start..end]
+                    .iter()
+                    .map(|x| x.norm())
+                    .fold(0.0, f32::max);
+
+                // The window halves everything, so the peak is scaled back up to match.
+                let amplitude = peak * 4.0 / SIZE as f32;
+                let decibels = 20.0 * amplitude.max(f32::MIN_POSITIVE).log10();
+
+                ((decibels + RANGE) / RANGE).clamp(0.0, 1.0)
+            })
+            .collect()
+    }
+}
+
+/// Passes through the samples of a source, while also copying them into a [Visualizer].
+pub struct Tap<S> {
+    input: S,
+    visualizer: Arc<Visualizer>,
+
+    /// Samples which haven't been handed to the [Visualizer] yet.
+    pending: Vec<f32>,
+
+    /// The sum of the samples in the current frame, alongside how many there have been.
+    frame: (f32, u16),
+}
+
+impl<S> Tap<S> {
+    pub fn new(input: S, visualizer: Arc<Visualizer>) -> Self {
+        Self {
+            input,
+            visualizer,
+            pending: Vec::with_capacity(BATCH),
+            frame: (0.0, 0),
+        }
+    }
+}
+
+impl<S> Iterator for Tap<S>
+where
+    S: Source,
+    S::Item: Sample,
+    f32: FromSample<S::Item>,
+{
+    type Item = S::Item;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let sample = self.input.next()?;
+
+        self.frame.0 += f32::from_sample_(sample);
+        self.frame.1 += 1;
+
+        if self.frame.1 >= self.input.channels() {
+            self.pending.push(self.frame.0 / f32::from(self.frame.1));
+            self.frame = (0.0, 0);
+
+            if self.pending.len() >= BATCH {
+                // This is set here, since the source might've been queued up long before it played.
+                self.visualizer
+                    .rate
+                    .store(self.input.sample_rate(), Ordering::Relaxed);
+                self.visualizer.push(&mut self.pending);
+            }
+        }
+
+        Some(sample)
+    }
+}
+
+impl<S> Source for Tap<S>
+where
+    S: Source,
+    S::Item: Sample,
+    f32: FromSample<S::Item>,
+{
+    fn current_frame_len(&self) -> Option<usize> {
+        self.input.current_frame_len()
+    }
+
+    fn channels(&self) -> u16 {
+        self.input.channels()
+    }
+
+    fn sample_rate(&self) -> u32 {
+        self.input.sample_rate()
+    }
+
+    fn total_duration(&self) -> Option<Duration> {
+        self.input.total_duration()
+    }
+
+    fn try_seek(&mut self, position: Duration) -> Result<(), SeekError> {
+        self.input.try_seek(position)
+    }
+}
@@ -2,35 +2,145 @@
 //! of tracks, as well as downloading them &
 //! finding new ones.
 
-use std::{io::Cursor, time::Duration};
+use std::{
+    io::{self, Cursor, Read, Seek, SeekFrom},
+    sync::Arc,
+    time::Duration,
+};
 
 use bytes::Bytes;
 use inflector::Inflector;
-use rand::Rng;
 use reqwest::Client;
-use rodio::{Decoder, Source};
+use rodio::{source::SeekError, Decoder, Source};
+use tokio::sync::OwnedSemaphorePermit;
 
-/// Downloads a raw track, but doesn't decode it.
-async fn download(track: &str, client: &Client) -> eyre::Result<Bytes> {
-    let url = format!("https://lofigirl.com/wp-content/uploads/{}", track);
-    let response = client.get(url).send().await?;
-    let data = response.bytes().await?;
+pub mod cache;
+pub mod lines;
+pub mod list;
+pub mod playlist;
+pub mod stream;
 
-    Ok(data)
+use list::{List, Progress};
+use stream::Stream;
+
+/// The loudness that tracks are adjusted to when normalizing, as an RMS amplitude.
+const TARGET_RMS: f32 = 0.1;
+
+/// The most that normalizing will ever amplify or quieten a track by.
+const MAX_GAIN: f32 = 4.0;
+
+/// A decoded [Data], which is decoded on its own thread if it's still being streamed.
+pub enum DecodedData {
+    Full(Decoder<Reader>),
+    Streaming(stream::Decoded),
+}
+
+impl DecodedData {
+    /// Gets the silence played during underruns, which only happen while streaming.
+    pub fn underruns(&self) -> Option<stream::Underruns> {
+        match self {
+            Self::Full(_) => None,
+            Self::Streaming(x) => Some(x.underruns()),
+        }
+    }
+}
+
+impl Iterator for DecodedData {
+    type Item = i16;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        match self {
+            Self::Full(x) => x.next(),
+            Self::Streaming(x) => x.next(),
+        }
+    }
+}
+
+impl Source for DecodedData {
+    fn current_frame_len(&self) -> Option<usize> {
+        match self {
+            Self::Full(x) => x.current_frame_len(),
+            Self::Streaming(x) => x.current_frame_len(),
+        }
+    }
+
+    fn channels(&self) -> u16 {
+        match self {
+            Self::Full(x) => x.channels(),
+            Self::Streaming(x) => x.channels(),
+        }
+    }
+
+    fn sample_rate(&self) -> u32 {
+        match self {
+            Self::Full(x) => x.sample_rate(),
+            Self::Streaming(x) => x.sample_rate(),
+        }
+    }
+
+    fn total_duration(&self) -> Option<Duration> {
+        match self {
+            Self::Full(x) => x.total_duration(),
+            Self::Streaming(x) => x.total_duration(),
+        }
+    }
+
+    fn try_seek(&mut self, position: Duration) -> Result<(), SeekError> {
+        match self {
+            Self::Full(x) => x.try_seek(position),
+            Self::Streaming(x) => x.try_seek(position),
+        }
+    }
+}
+
+/// The raw data of a track, which might still be arriving.
+#[derive(Clone)]
+pub enum Data {
+    /// The whole track, which `Bytes` makes cheap to clone.
+    Full(Bytes),
+
+    /// A track that's being streamed, see [Stream].
+    Streaming(Arc<Stream>),
+}
+
+impl Data {
+    /// Creates something that reads the track from the start.
+    pub fn reader(&self) -> Reader {
+        match self {
+            Self::Full(bytes) => Reader::Full(Cursor::new(bytes.clone())),
+            Self::Streaming(stream) => Reader::Streaming(stream.reader()),
+        }
+    }
+
+    /// Whether the track is still being downloaded.
+    pub fn is_streaming(&self) -> bool {
+        matches!(self, Self::Streaming(stream) if stream.is_streaming())
+    }
 }
 
-/// Gets a random track from `tracks.txt` and returns it.
-fn random() -> &'static str {
-    let tracks: Vec<&str> = include_str!("../data/tracks.txt")
-        .split_ascii_whitespace()
-        .collect();
+/// Reads from a [Data], which is what [rodio] actually decodes.
+pub enum Reader {
+    Full(Cursor<Bytes>),
+    Streaming(stream::Reader),
+}
 
-    let random = rand::thread_rng().gen_range(0..tracks.len());
-    tracks[random]
+impl Read for Reader {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        match self {
+            Self::Full(x) => x.read(buf),
+            Self::Streaming(x) => x.read(buf),
+        }
+    }
 }
 
-/// Just a shorthand for a decoded [Bytes].
-pub type DecodedData = Decoder<Cursor<Bytes>>;
+impl Seek for Reader {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        match self {
+            Self::Full(x) => x.seek(pos),
+            Self::Streaming(x) => x.seek(pos),
+        }
+    }
+}
 
 /// The TrackInfo struct, which has the name and duration of a track.
 ///
@@ -38,25 +148,42 @@ pub type DecodedData = Decoder<Cursor<Bytes>>;
 /// from the decoded data and not from the raw data.
 #[derive(Debug, PartialEq, Clone)]
 pub struct TrackInfo {
+    /// The identifier of the track, see [Track::id].
+    pub id: String,
+
     /// This is a formatted name, so it doesn't include the full path.
     pub name: String,
 
     /// The duration of the track, this is an [Option] because there are
     /// cases where the duration of a track is unknown.
     pub duration: Option<Duration>,
+
+    /// The gain which is applied to the track, which is only different from 1.0 when normalizing.
+    pub gain: f32,
+
+    /// The position of the track in the list, see [Track::index].
+    pub index: usize,
 }
 
 impl TrackInfo {
     /// Formats a name with [Inflector].
     /// This will also strip the first few numbers that are
     /// usually present on most lofi tracks.
-    fn format_name(name: &'static str) -> String {
-        let mut formatted = name
-            .split("/")
-            .nth(2)
-            .unwrap()
-            .strip_suffix(".mp3")
-            .unwrap()
+    pub fn format_name(name: &str) -> String {
+        let file = name.rsplit("/").next().unwrap_or(name);
+
+        // Any extension is stripped, as long as it actually looks like one.
+        let stem = file
+            .rsplit_once('.')
+            .filter(|(_, x)| {
+                x.len() <= 4
+                    && x.starts_with(|x: char| x.is_ascii_alphabetic())
+                    && x.chars().all(|x| x.is_ascii_alphanumeric())
+            })
+            .map_or(file, |(x, _)| x);
+
+        let formatted = stem
+            .replace('_', " ")
             .to_lowercase()
             .to_title_case()
             // Inflector doesn't like contractions...
@@ -73,9 +200,7 @@ impl TrackInfo {
         // This is incremented for each digit in front of the song name.
         let mut skip = 0;
 
-        // SAFETY: All of the track names originate with the `'static` lifetime,
-        // SAFETY: so basically this has already been checked.
-        for character in unsafe { formatted.as_bytes_mut() } {
+        for character in formatted.as_bytes() {
             if character.is_ascii_digit() {
                 skip += 1;
             } else {
@@ -86,11 +211,27 @@ impl TrackInfo {
         String::from(&formatted[skip..])
     }
 
-    /// Creates a new [`TrackInfo`] from a raw name & decoded track data.
-    pub fn new(name: &'static str, decoded: &DecodedData) -> Self {
+    /// Splits the name into an artist & title, if it follows the "Artist - Title" convention.
+    pub fn split_artist(&self) -> Option<(&str, &str)> {
+        let (artist, title) = self.name.split_once(" - ")?;
+        let (artist, title) = (artist.trim(), title.trim());
+
+        if artist.is_empty() || title.is_empty() {
+            None
+        } else {
+            Some((artist, title))
+        }
+    }
+
+    /// Creates a new [`TrackInfo`] from an identifier, a formatted name,
+    /// the position in the list & decoded track data.
+    pub fn new(id: String, name: String, index: usize, decoded: &DecodedData) -> Self {
         Self {
             duration: decoded.total_duration(),
-            name: Self::format_name(name),
+            gain: 1.0,
+            id,
+            name,
+            index,
         }
     }
 }
@@ -108,31 +249,109 @@ pub struct DecodedTrack {
 impl DecodedTrack {
     /// Creates a new track.
     /// This is equivalent to [Track::decode].
+    ///
+    /// This can block if the track is still being streamed, since the decoder reads the start of it.
     pub fn new(track: Track) -> eyre::Result<Self> {
-        let data = Decoder::new(Cursor::new(track.data))?;
-        let info = TrackInfo::new(track.name, &data);
+        let decoder = Decoder::new(track.data.reader())?;
+        let data = if track.data.is_streaming() {
+            DecodedData::Streaming(stream::Decoded::new(decoder))
+        } else {
+            DecodedData::Full(decoder)
+        };
+
+        let info = TrackInfo::new(track.id, track.name, track.index, &data);
 
         Ok(Self { info, data })
     }
 }
 
 /// The main track struct, which only includes data & the track name.
+#[derive(Clone)]
 pub struct Track {
-    /// This name is not formatted, and also includes the month & year of the track.
-    pub name: &'static str,
+    /// The full URL or path of the track, which uniquely identifies it.
+    pub id: String,
+
+    /// The formatted name of the track, which is what's displayed.
+    pub name: String,
+
+    /// The position of the track in the list, which is kept from when it was picked
+    /// so that it doesn't have to be searched for every time it's shown.
+    pub index: usize,
 
     /// The raw data of the track, which is not decoded and
     /// therefore much more memory efficient.
-    pub data: Bytes,
+    pub data: Data,
 }
 
 impl Track {
-    /// Fetches and downloads a random track from the tracklist.
-    pub async fn random(client: &Client) -> eyre::Result<Self> {
-        let name = random();
-        let data = download(name, client).await?;
+    /// Downloads the track at `index` in `list`, counting what's arrived in `progress`.
+    pub async fn download(
+        list: &List,
+        index: usize,
+        client: &Client,
+        progress: &Progress,
+    ) -> eyre::Result<Self> {
+        let track = list.get(index);
+        let data = list.download(track, client, progress).await?;
+
+        Ok(Self {
+            data: Data::Full(data),
+            id: list.id(track),
+            name: list.name(track),
+            index,
+        })
+    }
+
+    /// Like [Track::download], but it can start playing before the download is done.
+    ///
+    /// This only streams tracks which can be decoded as they arrive, and otherwise
+    /// it just downloads the whole thing first. See [List::stream] for `streamer` & `permit`.
+    pub async fn stream(
+        list: &List,
+        index: usize,
+        client: &Client,
+        streamer: &Client,
+        progress: Arc<Progress>,
+        permit: OwnedSemaphorePermit,
+    ) -> eyre::Result<Self> {
+        let track = list.get(index);
+        let data = list
+            .stream(track, client, streamer, progress, permit)
+            .await?;
+
+        Ok(Self {
+            data,
+            id: list.id(track),
+            name: list.name(track),
+            index,
+        })
+    }
+
+    /// Works out the gain which would bring this track to [TARGET_RMS].
+    ///
+    /// This is a simple RMS based approach, rather than something like EBU R128,
+    /// but it still has to decode the entire track, so it should be done in the background.
+    /// The gain is also limited so that the loudest peak in the track won't clip.
+    pub fn gain(&self) -> eyre::Result<f32> {
+        let decoder = Decoder::new(self.data.reader())?;
+
+        let (mut sum, mut count, mut peak) = (0.0f64, 0u64, 0.0f32);
+        for sample in decoder {
+            let sample = f32::from(sample) / f32::from(i16::MAX);
+            sum += f64::from(sample * sample);
+            count += 1;
+            peak = peak.max(sample.abs());
+        }
+
+        // A silent track shouldn't be amplified at all.
+        if sum == 0.0 {
+            return Ok(1.0);
+        }
 
-        Ok(Self { data, name })
+        let rms = (sum / count as f64).sqrt() as f32;
+        Ok((TARGET_RMS / rms)
+            .min(1.0 / peak)
+            .clamp(1.0 / MAX_GAIN, MAX_GAIN))
     }
 
     /// This will actually decode and format the track,
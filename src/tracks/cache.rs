@@ -0,0 +1,188 @@
+//! Contains the [Cache] struct, which keeps downloaded
+//! tracks on the disk so they don't have to be downloaded again.
+
+use std::{
+    fs::File,
+    path::PathBuf,
+    time::{Duration, SystemTime},
+};
+
+use bytes::Bytes;
+use reqwest::Url;
+use tokio::{fs, task};
+
+use crate::paths;
+
+/// The extension of files which are still being written.
+///
+/// Tracks are first written to a file with this extension, and then renamed,
+/// so that a track which was only partially written is never read.
+const PARTIAL: &str = "part";
+
+/// The directory inside of the cache which has copies of remote track lists.
+const LISTS: &str = "lists";
+
+/// How old a partially written file has to be before it's assumed to have been
+/// left behind by a download that was interrupted, rather than being written right now.
+const STALE: Duration = Duration::from_secs(10 * 60);
+
+/// The on-disk cache of downloaded tracks, which is usually in `~/.cache/lowfi`.
+#[derive(Clone)]
+pub struct Cache {
+    /// The directory where all of the cached tracks are.
+    dir: PathBuf,
+
+    /// The maximum size of the cache in bytes, after which the
+    /// least recently used tracks will be removed.
+    limit: u64,
+}
+
+impl Cache {
+    /// Creates the cache, with `limit` being the maximum size in megabytes.
+    pub async fn new(limit: u64) -> eyre::Result<Self> {
+        let dir = paths::cache()?;
+        if !dir.exists() {
+            fs::create_dir_all(&dir).await?;
+        }
+
+        let cache = Self {
+            dir,
+            limit: limit * 1024 * 1024,
+        };
+
+        let _ = cache.sweep().await;
+
+        Ok(cache)
+    }
+
+    /// Removes partially written files which have been around for longer than [STALE],
+    /// since they'd otherwise never be cleaned up.
+    async fn sweep(&self) -> eyre::Result<()> {
+        let mut entries = fs::read_dir(&self.dir).await?;
+
+        while let Some(entry) = entries.next_entry().await? {
+            let path = entry.path();
+            if path.extension().is_some_and(|x| x == PARTIAL) {
+                let modified = entry.metadata().await?.modified()?;
+                if modified.elapsed().is_ok_and(|x| x > STALE) {
+                    fs::remove_file(path).await?;
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Gets the file name in the cache for a track, which is a hash of the full URL.
+    ///
+    /// This uses FNV-1a, since it's simple & stays the same between versions of lowfi,
+    /// unlike the hasher in the standard library. The extension is kept, if there is one.
+    fn name(url: &Url) -> String {
+        let hash = url.as_str().bytes().fold(0xcbf29ce484222325u64, |hash, x| {
+            (hash ^ u64::from(x)).wrapping_mul(0x100000001b3)
+        });
+
+        let extension = url.path().rsplit_once('.').map(|(_, x)| x).filter(|x| {
+            !x.is_empty()
+                && x.len() <= 4
+                && *x != PARTIAL
+                && x.chars().all(|x| x.is_ascii_alphanumeric())
+        });
+
+        match extension {
+            Some(extension) => format!("{hash:016x}.{extension}"),
+            None => format!("{hash:016x}"),
+        }
+    }
+
+    /// Gets a track from the cache, if it's there.
+    ///
+    /// This also marks the track as recently used, so that it won't be evicted.
+    pub async fn get(&self, url: &Url) -> Option<Bytes> {
+        let path = self.dir.join(Self::name(url));
+        let data = fs::read(&path).await.ok()?;
+        if data.is_empty() {
+            return None;
+        }
+
+        // This marks the track as recently used, and errors are ignored.
+        let _ = task::spawn_blocking(move || {
+            File::options()
+                .append(true)
+                .open(path)?
+                .set_modified(SystemTime::now())
+        })
+        .await;
+
+        Some(Bytes::from(data))
+    }
+
+    /// Whether the track at `url` is in the cache.
+    pub fn contains(&self, url: &Url) -> bool {
+        self.dir.join(Self::name(url)).is_file()
+    }
+
+    /// Gets the copy of the track list at `url`, if it's been fetched before.
+    pub async fn list(&self, url: &Url) -> Option<String> {
+        fs::read_to_string(self.dir.join(LISTS).join(Self::name(url)))
+            .await
+            .ok()
+    }
+
+    /// Saves a copy of the track list at `url`, so that it can still be used without a network.
+    ///
+    /// Lists are kept in their own directory, so they're never evicted.
+    pub async fn store_list(&self, url: &Url, text: &str) -> eyre::Result<()> {
+        let dir = self.dir.join(LISTS);
+        if !dir.exists() {
+            fs::create_dir_all(&dir).await?;
+        }
+
+        fs::write(dir.join(Self::name(url)), text).await?;
+        Ok(())
+    }
+
+    /// Writes a track to the cache, and then evicts old tracks if the cache is too big.
+    pub async fn store(&self, url: &Url, data: &Bytes) -> eyre::Result<()> {
+        let name = Self::name(url);
+        let path = self.dir.join(&name);
+        let partial = self.dir.join(format!("{name}.{PARTIAL}"));
+
+        fs::write(&partial, data).await?;
+        fs::rename(partial, path).await?;
+
+        self.evict().await
+    }
+
+    /// Removes the least recently used tracks until the cache is under its limit.
+    async fn evict(&self) -> eyre::Result<()> {
+        let mut files = Vec::new();
+        let mut entries = fs::read_dir(&self.dir).await?;
+
+        while let Some(entry) = entries.next_entry().await? {
+            let path = entry.path();
+            if path.extension().is_some_and(|x| x == PARTIAL) {
+                continue;
+            }
+
+            let metadata = entry.metadata().await?;
+            if metadata.is_file() {
+                files.push((metadata.modified()?, metadata.len(), path));
+            }
+        }
+
+        let mut size: u64 = files.iter().map(|x| x.1).sum();
+        files.sort_by_key(|x| x.0);
+
+        for (_, len, path) in files {
+            if size <= self.limit {
+                break;
+            }
+
+            fs::remove_file(path).await?;
+            size -= len;
+        }
+
+        Ok(())
+    }
+}
@@ -0,0 +1,78 @@
+//! Has [Lines], which is how the tracks of a [List](super::list::List) are stored.
+//!
+//! Track lists can have tens of thousands of entries, so instead of a [String] for
+//! each one, the whole list is kept as a single string alongside where each track is in it.
+
+use std::{borrow::Cow, ops::Index};
+
+/// Every track in a list, which can be accessed by index without any extra allocations.
+#[derive(Default)]
+pub struct Lines {
+    /// The text that all of the tracks are slices of, which is usually the list exactly as it was read.
+    text: Cow<'static, str>,
+
+    /// The start & end of each track in `text`, in bytes.
+    spans: Vec<(usize, usize)>,
+}
+
+impl Lines {
+    /// Creates a list of tracks from `text`, where each span is the start & end of a track in it.
+    ///
+    /// Each span has to be in bounds, and start & end on character boundaries.
+    pub fn new(text: Cow<'static, str>, spans: Vec<(usize, usize)>) -> Self {
+        debug_assert!(spans
+            .iter()
+            .all(|(start, end)| text.get(*start..*end).is_some()));
+        Self { text, spans }
+    }
+
+    /// The amount of tracks.
+    pub fn len(&self) -> usize {
+        self.spans.len()
+    }
+
+    /// Whether there aren't any tracks at all.
+    pub fn is_empty(&self) -> bool {
+        self.spans.is_empty()
+    }
+
+    /// Goes through each of the tracks, in order.
+    pub fn iter(&self) -> impl Iterator<Item = &str> {
+        self.spans
+            .iter()
+            .map(|(start, end)| &self.text[*start..*end])
+    }
+
+    /// Only keeps the tracks which `keep` is true for.
+    ///
+    /// The text itself is left alone, since it's only the spans which need to change.
+    pub fn retain(&mut self, mut keep: impl FnMut(&str) -> bool) {
+        let text = &self.text;
+        self.spans.retain(|(start, end)| keep(&text[*start..*end]));
+    }
+}
+
+impl Index<usize> for Lines {
+    type Output = str;
+
+    fn index(&self, index: usize) -> &str {
+        let (start, end) = self.spans[index];
+        &self.text[start..end]
+    }
+}
+
+impl FromIterator<String> for Lines {
+    /// Joins each track onto a single line of the text, which is for lists that weren't read from a file.
+    fn from_iter<T: IntoIterator<Item = String>>(iter: T) -> Self {
+        let mut text = String::new();
+        let mut spans = Vec::new();
+
+        for track in iter {
+            spans.push((text.len(), text.len() + track.len()));
+            text.push_str(&track);
+            text.push('\n');
+        }
+
+        Self::new(Cow::Owned(text), spans)
+    }
+}
@@ -0,0 +1,1113 @@
+//! Contains the [List] struct, which is where
+//! lowfi picks all of its tracks from.
+
+use std::{
+    borrow::Cow,
+    collections::{HashMap, HashSet},
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering},
+        Arc, OnceLock, RwLock,
+    },
+    time::Duration,
+};
+
+use bytes::{Bytes, BytesMut};
+use eyre::{bail, eyre};
+use futures::future::select_ok;
+use rand::{distributions::WeightedIndex, prelude::Distribution, Rng};
+use reqwest::{header::RANGE, Client, Response, StatusCode, Url};
+use tokio::{fs, sync::OwnedSemaphorePermit};
+
+use super::{cache::Cache, lines::Lines, playlist::Playlist, stream::Stream, Data};
+
+/// The URL that all of the tracks in the built-in list are relative to.
+pub const BASE_URL: &str = "https://lofigirl.com/wp-content/uploads/";
+
+/// The name that's shown for a track whose name would otherwise be blank.
+pub const UNTITLED: &str = "(untitled)";
+
+/// The file extensions which lowfi is able to decode & play.
+const EXTENSIONS: [&str; 1] = ["mp3"];
+
+/// How many times a track will be attempted to be downloaded before giving up on it.
+const ATTEMPTS: u32 = 3;
+
+/// How long to wait before retrying a download, which doubles after each failed attempt.
+const RETRY_DELAY: Duration = Duration::from_millis(500);
+
+/// How long to wait when checking whether there's a network connection.
+const PROBE_TIMEOUT: Duration = Duration::from_secs(3);
+
+/// Where the tracks of a [List] are actually stored.
+enum Source {
+    /// The tracks are on the web, relative to this URL.
+    Remote(Url),
+
+    /// Each track is either a full path to a file on the disk, or a full URL.
+    Local,
+}
+
+/// What happens once the last track in the list has been picked while not shuffling, from `--at-end`.
+#[derive(clap::ValueEnum, PartialEq, Debug, Clone, Copy)]
+pub enum AtEnd {
+    /// Start again from the top of the list.
+    Loop,
+
+    /// Quit once the last track is over.
+    Stop,
+
+    /// Switch to shuffling.
+    Shuffle,
+}
+
+/// How far along a single download is, which is shown while it's being waited on.
+#[derive(Default)]
+pub struct Progress {
+    /// Whether a response has arrived yet, since the other fields are meaningless until then.
+    started: AtomicBool,
+
+    /// How many bytes have arrived so far.
+    downloaded: AtomicU64,
+
+    /// The size of the download in bytes, which is zero if it isn't known.
+    length: AtomicU64,
+}
+
+impl Progress {
+    /// Starts counting from zero again, since a response with a size of `length` just arrived.
+    fn start(&self, length: Option<u64>) {
+        self.downloaded.store(0, Ordering::Relaxed);
+        self.length.store(length.unwrap_or(0), Ordering::Relaxed);
+        self.started.store(true, Ordering::Relaxed);
+    }
+
+    /// Counts another chunk which is `amount` bytes.
+    fn add(&self, amount: usize) {
+        self.downloaded.fetch_add(amount as u64, Ordering::Relaxed);
+    }
+
+    /// Gets how many bytes have arrived, alongside the size of the download if it's known.
+    ///
+    /// This is [None] until the server has responded.
+    pub fn get(&self) -> Option<(u64, Option<u64>)> {
+        if !self.started.load(Ordering::Relaxed) {
+            return None;
+        }
+
+        let length = self.length.load(Ordering::Relaxed);
+        Some((
+            self.downloaded.load(Ordering::Relaxed),
+            (length > 0).then_some(length),
+        ))
+    }
+}
+
+/// The tracks which can be picked while shuffling, alongside their weights.
+///
+/// This is built the first time it's needed, and then again whenever a track is blocked,
+/// since building it means going through every track, which adds up for big lists.
+struct Weighted {
+    /// The indices of the tracks which aren't blocked.
+    candidates: Vec<usize>,
+
+    /// The weights of `candidates`, which is [None] if they're all 0, so they're equally likely.
+    index: Option<WeightedIndex<u32>>,
+}
+
+/// Adds one to a counter for as long as it's around, which is how the
+/// downloads being retried are counted, even if one of them is cancelled.
+struct Counted<'a>(&'a AtomicUsize);
+
+impl<'a> Counted<'a> {
+    fn new(counter: &'a AtomicUsize) -> Self {
+        counter.fetch_add(1, Ordering::Relaxed);
+        Self(counter)
+    }
+}
+
+impl Drop for Counted<'_> {
+    fn drop(&mut self) {
+        self.0.fetch_sub(1, Ordering::Relaxed);
+    }
+}
+
+/// A list of tracks, which can either be on the web or on the disk.
+pub struct List {
+    /// Where the tracks are stored, which is needed to actually download them.
+    source: Source,
+
+    /// Each of the tracks in the list.
+    lines: Lines,
+
+    /// How likely each track is to be picked while shuffling, compared to the rest.
+    /// Tracks which aren't in here have a weight of 1, which is most of them.
+    weights: HashMap<String, u32>,
+
+    /// The names that tracks were given by a playlist, which are shown instead of their file names.
+    titles: HashMap<String, String>,
+
+    /// The cache which remote tracks are saved to, if caching is enabled.
+    cache: Option<Cache>,
+
+    /// Whether tracks are picked randomly, instead of in order.
+    shuffle: AtomicBool,
+
+    /// The index of the next track to pick when not shuffling.
+    position: AtomicUsize,
+
+    /// The indices of the tracks which have been blocked, and so should never be picked.
+    blocked: RwLock<HashSet<usize>>,
+
+    /// What [List::shuffled] picks from, which is [None] until it's needed, or after `blocked` changes.
+    weighted: RwLock<Option<Arc<Weighted>>>,
+
+    /// The index of each track by its identifier, which is only worked out once it's needed.
+    ids: OnceLock<HashMap<String, usize>>,
+
+    /// How many downloads are currently being retried.
+    retrying: AtomicUsize,
+
+    /// Other URLs which have the same tracks as a remote source, which are tried if it can't be reached.
+    /// The identifiers of the tracks are still relative to the source itself.
+    mirrors: Vec<Url>,
+
+    /// The base URL that downloads are tried from first, where 0 is the source & the rest are `mirrors`.
+    mirror: AtomicUsize,
+
+    /// Whether names are shown exactly as they are in the list, from `--raw-names`.
+    raw: bool,
+
+    /// What to do after the last track when not shuffling.
+    at_end: AtEnd,
+
+    /// Whether the list itself was just downloaded, in which case there's definitely a network.
+    reachable: bool,
+}
+
+impl List {
+    /// Creates a list of `lines` from `source`, where everything else is left as the default.
+    ///
+    /// Each of the other constructors starts from this, which is where new fields get their defaults.
+    fn new(source: Source, lines: Lines) -> Self {
+        Self {
+            source,
+            lines,
+            weights: HashMap::new(),
+            titles: HashMap::new(),
+            cache: None,
+            shuffle: AtomicBool::new(true),
+            position: AtomicUsize::new(0),
+            blocked: RwLock::new(HashSet::new()),
+            weighted: RwLock::new(None),
+            ids: OnceLock::new(),
+            retrying: AtomicUsize::new(0),
+            mirrors: Vec::new(),
+            mirror: AtomicUsize::new(0),
+            raw: false,
+            at_end: AtEnd::Loop,
+            reachable: false,
+        }
+    }
+
+    /// The built-in list, which is just `tracks.txt` from the lofi girl file server.
+    pub fn builtin() -> Self {
+        Self::new(
+            // SAFETY: `BASE_URL` is a constant which is known to be valid.
+            Source::Remote(Url::parse(BASE_URL).unwrap()),
+            Self::parse(Cow::Borrowed(include_str!("../../data/tracks.txt"))).0,
+        )
+    }
+
+    /// Splits the contents of a track list into each of its tracks, alongside their weights.
+    ///
+    /// A line can end with a tab & then a weight, like `track.mp3\t3`, which makes that
+    /// track three times as likely to be picked. Lines without a valid weight have a weight of 1.
+    ///
+    /// Lines starting with `#` are comments, and lists written on Windows are fine too,
+    /// since a leading BOM is removed & the `\r` of each line is trimmed along with everything else.
+    ///
+    /// The tracks are kept as slices of `text`, rather than being copied out of it one by one.
+    fn parse(text: Cow<'static, str>) -> (Lines, HashMap<String, u32>) {
+        let mut weights = HashMap::new();
+        let mut spans = Vec::new();
+
+        let mut offset = text.len() - text.trim_start_matches('\u{feff}').len();
+        for raw in text[offset..].split_inclusive('\n') {
+            let start = offset + raw.len() - raw.trim_start().len();
+            offset += raw.len();
+
+            let line = raw.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            let weighted = line
+                .rsplit_once('\t')
+                .and_then(|(track, weight)| Some((track.trim_end(), weight.parse().ok()?)));
+
+            let track = match weighted {
+                Some((track, weight)) => {
+                    if weight != 1 {
+                        weights.insert(track.to_owned(), weight);
+                    }
+
+                    track
+                }
+                None => line,
+            };
+
+            spans.push((start, start + track.len()));
+        }
+
+        (Lines::new(text, spans), weights)
+    }
+
+    /// Gets `track` as a URL, if it's a full URL rather than a path.
+    fn url(track: &str) -> Option<Url> {
+        Url::parse(track)
+            .ok()
+            .filter(|x| matches!(x.scheme(), "http" | "https"))
+    }
+
+    /// Downloads a list from the first of `urls`, where each line is a track that's relative to the URL.
+    /// The rest of `urls` are mirrors, which are tried in order if the ones before them can't be reached.
+    ///
+    /// A copy of the list is saved to `cache`, which is used instead if none of them can be reached.
+    pub async fn from_urls(
+        mut urls: Vec<Url>,
+        client: &Client,
+        cache: Option<&Cache>,
+    ) -> eyre::Result<Self> {
+        if urls.is_empty() {
+            bail!("there aren't any track lists to fetch");
+        }
+
+        let url = urls.remove(0);
+        let mut mirror = 0;
+        let mut fetched = Err(eyre!("couldn't fetch the track list"));
+        for (i, base) in std::iter::once(&url).chain(&urls).enumerate() {
+            fetched = async {
+                client
+                    .get(base.clone())
+                    .send()
+                    .await?
+                    .error_for_status()?
+                    .text()
+                    .await
+            }
+            .await
+            .map_err(|error| eyre!("couldn't fetch the track list from {base}: {error}"));
+
+            if fetched.is_ok() {
+                mirror = i;
+                break;
+            }
+        }
+
+        let reachable = fetched.is_ok();
+        let text = match (fetched, cache) {
+            (Ok(text), Some(cache)) => {
+                let _ = cache.store_list(&url, &text).await;
+                text
+            }
+            (Ok(text), None) => text,
+            (Err(error), Some(cache)) => cache.list(&url).await.ok_or(error)?,
+            (Err(error), None) => return Err(error),
+        };
+
+        let (lines, weights) = Self::parse(Cow::Owned(text));
+        if lines.is_empty() {
+            bail!("the track list at {url} doesn't have any tracks");
+        }
+
+        Ok(Self {
+            weights,
+            mirrors: urls,
+            mirror: AtomicUsize::new(mirror),
+            reachable,
+            ..Self::new(Source::Remote(url), lines)
+        })
+    }
+
+    /// Reads a list from the file at `path`, where each line is a full path or URL.
+    ///
+    /// This is what's used for `bookmarks.txt`.
+    pub async fn from_file(path: &Path) -> eyre::Result<Self> {
+        let text = fs::read_to_string(path)
+            .await
+            .map_err(|error| eyre!("couldn't read {}: {error}", path.display()))?;
+
+        let (lines, weights) = Self::parse(Cow::Owned(text));
+        if lines.is_empty() {
+            bail!("{} doesn't have any tracks", path.display());
+        }
+
+        Ok(Self {
+            weights,
+            ..Self::new(Source::Local, lines)
+        })
+    }
+
+    /// Reads a `.m3u`, `.m3u8` or `.pls` playlist from `path`, which can have both paths & URLs.
+    ///
+    /// Entries which can't be played are skipped, and how many were skipped is returned too.
+    pub async fn from_playlist(path: &Path) -> eyre::Result<(Self, usize)> {
+        let text = fs::read_to_string(path)
+            .await
+            .map_err(|error| eyre!("couldn't read {}: {error}", path.display()))?;
+
+        let playlist = Playlist::parse(path, &text);
+        if playlist.entries.is_empty() {
+            bail!(
+                "{} doesn't have any tracks that can be played",
+                path.display()
+            );
+        }
+
+        let titles = playlist
+            .entries
+            .iter()
+            .filter_map(|x| Some((x.track.clone(), x.title.clone()?)))
+            .collect();
+
+        let lines = playlist.entries.into_iter().map(|x| x.track).collect();
+        let list = Self {
+            titles,
+            ..Self::new(Source::Local, lines)
+        };
+
+        Ok((list, playlist.skipped))
+    }
+
+    /// Recursively finds all of the playable files in `dir`, and creates a list from them.
+    ///
+    /// This is done with a stack instead of recursion, since async rust doesn't like recursive functions.
+    pub async fn from_dir(dir: &Path) -> eyre::Result<Self> {
+        let mut lines = Vec::new();
+        let mut dirs = vec![dir.to_path_buf()];
+
+        while let Some(dir) = dirs.pop() {
+            let mut entries = fs::read_dir(&dir)
+                .await
+                .map_err(|error| eyre!("couldn't read {}: {error}", dir.display()))?;
+
+            while let Some(entry) = entries.next_entry().await? {
+                let path = entry.path();
+
+                if entry.file_type().await?.is_dir() {
+                    dirs.push(path);
+                } else if path
+                    .extension()
+                    .and_then(|x| x.to_str())
+                    .is_some_and(|x| EXTENSIONS.contains(&x.to_lowercase().as_str()))
+                {
+                    lines.push(path.to_string_lossy().into_owned());
+                }
+            }
+        }
+
+        if lines.is_empty() {
+            bail!("couldn't find any playable files in {}", dir.display());
+        }
+
+        // The order of `read_dir` isn't guaranteed, which matters when playing sequentially.
+        lines.sort();
+
+        Ok(Self::new(Source::Local, lines.into_iter().collect()))
+    }
+
+    /// Changes what happens after the last track in the list, which otherwise just loops.
+    pub fn with_at_end(mut self, at_end: AtEnd) -> Self {
+        self.at_end = at_end;
+        self
+    }
+
+    /// Shows the names of tracks exactly as they are in the list, instead of cleaning them up.
+    pub fn with_raw_names(mut self) -> Self {
+        self.raw = true;
+        self
+    }
+
+    /// Enables caching of remote tracks in `cache`.
+    pub fn with_cache(mut self, cache: Cache) -> Self {
+        self.cache = Some(cache);
+        self
+    }
+
+    /// Checks whether the tracks in the list can actually be downloaded,
+    /// which is always true for lists that are only on the disk.
+    ///
+    /// Any response at all counts, even an error, since that still means there's a network.
+    /// Nothing is sent if the list was only just downloaded, since that already proves it.
+    pub async fn is_online(&self, client: &Client) -> bool {
+        let Source::Remote(base) = &self.source else {
+            return true;
+        };
+
+        if self.reachable {
+            return true;
+        }
+
+        // Every mirror is tried at once, so that a few unreachable ones don't hold up startup.
+        let probes = std::iter::once(base).chain(&self.mirrors).map(|base| {
+            Box::pin(async move {
+                client
+                    .head(base.clone())
+                    .timeout(PROBE_TIMEOUT)
+                    .send()
+                    .await
+                    .map(|_| ())
+            })
+        });
+
+        select_ok(probes).await.is_ok()
+    }
+
+    /// Gets the base URL which tracks are being downloaded from, which
+    /// is only different from the list's own URL if a mirror is being used.
+    pub fn mirror(&self) -> Option<&Url> {
+        let Source::Remote(base) = &self.source else {
+            return None;
+        };
+
+        match self.mirror.load(Ordering::Relaxed) {
+            0 => Some(base),
+            x => self.mirrors.get(x - 1),
+        }
+    }
+
+    /// Removes every remote track which isn't in the cache, since there's no network to download them.
+    ///
+    /// This is an error if there's nothing left to play afterwards.
+    pub fn offline(mut self) -> eyre::Result<Self> {
+        let (source, cache) = (&self.source, &self.cache);
+        self.lines.retain(|x| match (source, Self::url(x)) {
+            (Source::Remote(base), _) => base
+                .join(x)
+                .is_ok_and(|url| cache.as_ref().is_some_and(|c| c.contains(&url))),
+            (Source::Local, Some(url)) => cache.as_ref().is_some_and(|c| c.contains(&url)),
+            (Source::Local, None) => true,
+        });
+
+        if self.lines.is_empty() {
+            bail!("there's no network and no cached tracks, so there's nothing to play");
+        }
+
+        self.ids = OnceLock::new();
+        Ok(self)
+    }
+
+    /// Removes every track with a name which doesn't contain `filter`, ignoring case.
+    ///
+    /// This is an error if none of the tracks match, since there wouldn't be anything to play.
+    pub fn with_filter(mut self, filter: &str) -> eyre::Result<Self> {
+        let filter = filter.to_lowercase();
+        let mut lines = std::mem::take(&mut self.lines);
+        lines.retain(|x| self.name(x).to_lowercase().contains(&filter));
+        self.lines = lines;
+
+        if self.lines.is_empty() {
+            bail!("none of the tracks in the list match `{filter}`");
+        }
+
+        self.ids = OnceLock::new();
+        Ok(self)
+    }
+
+    /// Blocks all of the tracks in `ids`, which is usually the contents of `blocklist.txt`.
+    ///
+    /// This is an error if every single track in the list ends up blocked,
+    /// since there wouldn't be anything left to play.
+    pub fn with_blocklist(self, ids: &[String]) -> eyre::Result<Self> {
+        if ids.is_empty() {
+            return Ok(self);
+        }
+
+        let blocked: HashSet<usize> = ids.iter().filter_map(|x| self.index(x)).collect();
+        if blocked.len() == self.lines.len() {
+            bail!("every track in the list has been blocked, so there's nothing to play");
+        }
+
+        *self.blocked.write().unwrap() = blocked;
+        *self.weighted.write().unwrap() = None;
+        Ok(self)
+    }
+
+    /// Blocks the track with `id`, so that it won't be picked again.
+    ///
+    /// This refuses to block the last track which isn't already blocked,
+    /// and returns whether the track is now blocked.
+    pub fn block(&self, id: &str) -> bool {
+        let Some(index) = self.index(id) else {
+            return true;
+        };
+
+        let mut blocked = self.blocked.write().unwrap();
+        if !blocked.contains(&index) && blocked.len() + 1 >= self.lines.len() {
+            return false;
+        }
+
+        if blocked.insert(index) {
+            *self.weighted.write().unwrap() = None;
+        }
+
+        true
+    }
+
+    /// Whether the track with `id` has been blocked, either at startup or with [List::block].
+    pub fn is_blocked(&self, id: &str) -> bool {
+        self.index(id)
+            .is_some_and(|x| self.blocked.read().unwrap().contains(&x))
+    }
+
+    /// Gets the next track from the list, which is random unless shuffling is off,
+    /// in which case the weights of the tracks are ignored.
+    ///
+    /// Blocked tracks are never picked, and there's always at least one track
+    /// which isn't blocked, thanks to [List::block] & [List::with_blocklist].
+    /// This is only [None] once the end of the list is reached with [AtEnd::Stop],
+    /// and otherwise it's the index of the track, see [List::get].
+    pub fn next(&self) -> Option<usize> {
+        if !self.is_shuffled() {
+            match self.sequential() {
+                Some(index) => return Some(index),
+                None if self.at_end == AtEnd::Shuffle => self.set_shuffle(true),
+                None => return None,
+            }
+        }
+
+        Some(self.shuffled(&mut rand::thread_rng()))
+    }
+
+    /// Picks a random track which isn't blocked using `rng`, following the weights of the tracks.
+    fn shuffled(&self, rng: &mut impl Rng) -> usize {
+        let blocked = self.blocked.read().unwrap();
+        let available = |x: &usize| !blocked.contains(x);
+
+        // Without any weights, tracks can just be picked until one of them isn't blocked,
+        // which is much quicker for big lists than going through all of them.
+        if self.weights.is_empty() && blocked.len() * 2 < self.lines.len() {
+            loop {
+                let index = rng.gen_range(0..self.lines.len());
+                if available(&index) {
+                    return index;
+                }
+            }
+        }
+
+        let cached = self.weighted.read().unwrap().clone();
+        let weighted = cached.unwrap_or_else(|| {
+            let candidates: Vec<usize> = (0..self.lines.len()).filter(available).collect();
+            let weights = candidates
+                .iter()
+                .map(|x| self.weights.get(&self.lines[*x]).copied().unwrap_or(1));
+
+            // Weighting only fails if every weight is 0, in which case they're all equally likely.
+            let index = WeightedIndex::new(weights).ok();
+            let weighted = Arc::new(Weighted { candidates, index });
+            *self.weighted.write().unwrap() = Some(Arc::clone(&weighted));
+
+            weighted
+        });
+
+        match &weighted.index {
+            Some(index) => weighted.candidates[index.sample(rng)],
+            None => weighted.candidates[rng.gen_range(0..weighted.candidates.len())],
+        }
+    }
+
+    /// Gets the index of the next track when not shuffling, which is [None] if the
+    /// end of the list has been reached and it isn't meant to loop around.
+    fn sequential(&self) -> Option<usize> {
+        let blocked = self.blocked.read().unwrap();
+        let len = self.lines.len();
+
+        for _ in 0..len {
+            let position = self.position.fetch_add(1, Ordering::Relaxed);
+            if position >= len && self.at_end != AtEnd::Loop {
+                // This stays at the end, so that it doesn't eventually overflow.
+                self.position.store(len, Ordering::Relaxed);
+                return None;
+            }
+
+            if !blocked.contains(&(position % len)) {
+                return Some(position % len);
+            }
+        }
+
+        Some(0)
+    }
+
+    /// Whether every track has been picked with [AtEnd::Stop], so there's nothing left to play.
+    pub fn is_finished(&self) -> bool {
+        self.at_end == AtEnd::Stop
+            && !self.is_shuffled()
+            && self.position.load(Ordering::Relaxed) >= self.lines.len()
+    }
+
+    /// Finds a track which isn't blocked, either by its position in the list if `query`
+    /// is a number, or otherwise by the first name which contains `query`, ignoring case.
+    ///
+    /// Positions start from 1, which is how they're shown in the UI, but this
+    /// still gives the index of the track, just like [List::next].
+    pub fn find(&self, query: &str) -> Option<usize> {
+        let blocked = self.blocked.read().unwrap();
+
+        let index = match query.parse::<usize>() {
+            Ok(position) => position.checked_sub(1).filter(|x| *x < self.lines.len()),
+            Err(_) => {
+                let query = query.to_lowercase();
+                self.lines
+                    .iter()
+                    .position(|x| self.name(x).to_lowercase().contains(&query))
+            }
+        };
+
+        index.filter(|x| !blocked.contains(x))
+    }
+
+    /// Gets the track at `index` in the list, exactly as it's written there.
+    pub fn get(&self, index: usize) -> &str {
+        &self.lines[index]
+    }
+
+    /// The amount of tracks in the list.
+    pub fn len(&self) -> usize {
+        self.lines.len()
+    }
+
+    /// Whether tracks are currently being picked randomly.
+    pub fn is_shuffled(&self) -> bool {
+        self.shuffle.load(Ordering::Relaxed)
+    }
+
+    /// Sets whether tracks should be picked randomly, or in the order of the list.
+    pub fn set_shuffle(&self, shuffle: bool) {
+        self.shuffle.store(shuffle, Ordering::Relaxed);
+    }
+
+    /// Makes the track after `id` the next one to be picked when not shuffling.
+    ///
+    /// If `id` isn't in the list, then the position is left alone.
+    pub fn continue_from(&self, id: &str) {
+        if let Some(index) = self.index(id) {
+            self.position.store(index + 1, Ordering::Relaxed);
+        }
+    }
+
+    /// Finds the index of the track with `id` in the list.
+    ///
+    /// Working out the identifier of every track is slow for a big list,
+    /// so that's only done the first time, and then they're all kept.
+    pub fn index(&self, id: &str) -> Option<usize> {
+        let ids = self.ids.get_or_init(|| {
+            let mut ids = HashMap::with_capacity(self.lines.len());
+            for (index, track) in self.lines.iter().enumerate() {
+                // The first of any duplicates is the one that's found, just like searching for it.
+                ids.entry(self.id(track)).or_insert(index);
+            }
+
+            ids
+        });
+
+        ids.get(id).copied()
+    }
+
+    /// Gets a unique identifier for `track`, which is either the full URL or path.
+    pub fn id(&self, track: &str) -> String {
+        match &self.source {
+            Source::Remote(base) => base
+                .join(track)
+                .map_or_else(|_| track.to_owned(), String::from),
+            Source::Local => track.to_owned(),
+        }
+    }
+
+    /// Gets the name that should be displayed for `track`, which is its title if a playlist gave it one.
+    ///
+    /// This is also what's used for MPRIS, notifications & scrobbling, although
+    /// never for anything like caching, which uses [List::id] instead.
+    /// Names which end up empty, like a track that's only numbers, are [UNTITLED] instead.
+    pub fn name(&self, track: &str) -> String {
+        let file = || track.rsplit('/').next().unwrap_or(track).to_owned();
+
+        let name = match &self.source {
+            _ if self.raw => file(),
+            _ if self.titles.contains_key(track) => self.titles[track].clone(),
+            Source::Remote(_) => super::TrackInfo::format_name(track),
+            Source::Local if Self::url(track).is_some() => super::TrackInfo::format_name(track),
+            // Local files usually have names that were chosen on purpose, so they're mostly left alone.
+            Source::Local => PathBuf::from(track)
+                .file_stem()
+                .map_or_else(file, |x| x.to_string_lossy().replace('_', " ")),
+        };
+
+        if name.trim().is_empty() {
+            String::from(UNTITLED)
+        } else {
+            name
+        }
+    }
+
+    /// Whether any downloads are currently being retried after failing.
+    pub fn is_retrying(&self) -> bool {
+        self.retrying.load(Ordering::Relaxed) > 0
+    }
+
+    /// Downloads `url`, retrying up to [ATTEMPTS] times with an exponential backoff.
+    ///
+    /// Client errors like a 404 aren't retried, since they won't fix themselves.
+    /// How much has arrived is written to `progress`, which starts over with each attempt.
+    async fn fetch(
+        &self,
+        url: &Url,
+        client: &Client,
+        progress: &Progress,
+    ) -> reqwest::Result<Bytes> {
+        let mut attempt = 0;
+        // This is dropped once the download is over, even if that's because it was cancelled.
+        let mut retrying = None;
+
+        loop {
+            let result: reqwest::Result<Bytes> = async {
+                let mut response = client.get(url.clone()).send().await?.error_for_status()?;
+                progress.start(response.content_length());
+
+                // This is read in chunks, so that the progress can be shown while it's downloading.
+                let mut data = BytesMut::new();
+                while let Some(chunk) = response.chunk().await? {
+                    data.extend_from_slice(&chunk);
+                    progress.add(chunk.len());
+                }
+
+                Ok(data.freeze())
+            }
+            .await;
+
+            attempt += 1;
+            match result {
+                Err(error)
+                    if attempt < ATTEMPTS
+                        && !error.status().is_some_and(|x| x.is_client_error()) =>
+                {
+                    retrying.get_or_insert_with(|| Counted::new(&self.retrying));
+                    tokio::time::sleep(RETRY_DELAY * 2u32.pow(attempt - 1)).await;
+                }
+                result => return result,
+            }
+        }
+    }
+
+    /// Downloads `track` from `base` or one of the mirrors, starting with whichever worked last.
+    async fn fetch_mirrored(
+        &self,
+        base: &Url,
+        track: &str,
+        client: &Client,
+        progress: &Progress,
+    ) -> eyre::Result<Bytes> {
+        let bases: Vec<&Url> = std::iter::once(base).chain(&self.mirrors).collect();
+        let start = self.mirror.load(Ordering::Relaxed);
+
+        let mut result = Err(eyre!("there's nowhere to download {track} from"));
+        for i in (0..bases.len()).map(|x| (start + x) % bases.len()) {
+            result = self
+                .fetch(&bases[i].join(track)?, client, progress)
+                .await
+                .map_err(eyre::Report::from);
+
+            if result.is_ok() {
+                self.mirror.store(i, Ordering::Relaxed);
+                break;
+            }
+        }
+
+        result
+    }
+
+    /// Downloads a raw track, but doesn't decode it.
+    ///
+    /// If the track is a local file, then this just reads it, and if
+    /// the track is already in the cache, then it's read from there.
+    /// Anything which is actually downloaded is counted in `progress`.
+    pub async fn download(
+        &self,
+        track: &str,
+        client: &Client,
+        progress: &Progress,
+    ) -> eyre::Result<Bytes> {
+        let url = match &self.source {
+            Source::Remote(base) => base.join(track)?,
+            Source::Local => match Self::url(track) {
+                Some(url) => url,
+                None => return Ok(Bytes::from(fs::read(track).await?)),
+            },
+        };
+
+        if let Some(cache) = &self.cache {
+            if let Some(data) = cache.get(&url).await {
+                return Ok(data);
+            }
+        }
+
+        let data = match &self.source {
+            Source::Remote(base) => self.fetch_mirrored(base, track, client, progress).await?,
+            Source::Local => self.fetch(&url, client, progress).await?,
+        };
+
+        if let Some(cache) = &self.cache {
+            let _ = cache.store(&url, &data).await;
+        }
+
+        Ok(data)
+    }
+
+    /// Requests the rest of `url` from byte `from` onwards, after a stream broke off partway through,
+    /// which is tried up to [ATTEMPTS] times with an exponential backoff.
+    ///
+    /// The second value is how many bytes at the start of the response are already in the stream,
+    /// which is only more than zero if the server ignored the range & sent everything again.
+    async fn resume(client: &Client, url: &Url, from: usize) -> Option<(Response, usize)> {
+        for attempt in 0..ATTEMPTS {
+            tokio::time::sleep(RETRY_DELAY * 2u32.pow(attempt)).await;
+
+            let response = client
+                .get(url.clone())
+                .header(RANGE, format!("bytes={from}-"))
+                .send()
+                .await
+                .and_then(Response::error_for_status);
+
+            if let Ok(response) = response {
+                let skip = if response.status() == StatusCode::PARTIAL_CONTENT {
+                    0
+                } else {
+                    from
+                };
+
+                return Some((response, skip));
+            }
+        }
+
+        None
+    }
+
+    /// Starts downloading a track in the background, so that it can be played as it arrives.
+    ///
+    /// Only remote MP3s which aren't cached are streamed, since other formats might need
+    /// the whole file to be decoded. Everything else, including a track whose first request
+    /// fails, falls back to [List::download] with `client`, which also deals with retries & mirrors.
+    ///
+    /// The stream itself uses `streamer`, which shouldn't have a total timeout, since the
+    /// response lasts as long as the track does. If it breaks off, then it's resumed from
+    /// wherever it got to with [List::resume], which only gives up after [ATTEMPTS] tries in a row.
+    ///
+    /// `permit` is held onto until the download is done, even if that's well after this returns.
+    pub async fn stream(
+        &self,
+        track: &str,
+        client: &Client,
+        streamer: &Client,
+        progress: Arc<Progress>,
+        permit: OwnedSemaphorePermit,
+    ) -> eyre::Result<Data> {
+        let (url, key) = match (&self.source, self.mirror()) {
+            (Source::Remote(base), Some(mirror)) => (mirror.join(track)?, base.join(track)?),
+            (_, _) => match Self::url(track) {
+                Some(url) => (url.clone(), url),
+                None => {
+                    return self
+                        .download(track, client, &progress)
+                        .await
+                        .map(Data::Full)
+                }
+            },
+        };
+
+        let cached = self.cache.as_ref().is_some_and(|x| x.contains(&key));
+        if cached || !url.path().to_lowercase().ends_with(".mp3") {
+            return self
+                .download(track, client, &progress)
+                .await
+                .map(Data::Full);
+        }
+
+        let response = streamer
+            .get(url.clone())
+            .send()
+            .await
+            .and_then(Response::error_for_status);
+
+        let Ok(mut response) = response else {
+            return self
+                .download(track, client, &progress)
+                .await
+                .map(Data::Full);
+        };
+
+        let stream = Stream::new(response.content_length());
+        let cache = self.cache.clone();
+        progress.start(response.content_length());
+
+        let writer = Arc::downgrade(&stream);
+        let streamer = streamer.clone();
+        let task = tokio::spawn(async move {
+            // The download still counts towards `--buffer` until it's done.
+            let _permit = permit;
+
+            // How much of the response is already in the stream, from before it was resumed.
+            let mut skip = 0;
+
+            // Where the stream was last resumed from, so that it isn't resumed forever if nothing arrives.
+            let mut resumed = None;
+
+            let failed = loop {
+                let chunk = response.chunk().await;
+
+                // Nothing is going to play the track anymore, so there's no point finishing it.
+                let Some(writer) = writer.upgrade() else {
+                    return;
+                };
+
+                match chunk {
+                    Ok(Some(chunk)) => {
+                        let skipped = skip.min(chunk.len());
+                        skip -= skipped;
+
+                        writer.push(&chunk[skipped..]);
+                        progress.add(chunk.len() - skipped);
+                    }
+                    Ok(None) => break false,
+                    Err(_) if resumed == Some(writer.downloaded()) => break true,
+                    Err(_) => {
+                        let from = writer.downloaded();
+                        resumed = Some(from);
+
+                        match Self::resume(&streamer, &url, from).await {
+                            Some((next, skipped)) => (response, skip) = (next, skipped),
+                            None => break true,
+                        }
+                    }
+                }
+            };
+
+            let Some(writer) = writer.upgrade() else {
+                return;
+            };
+
+            if let (Some(data), Some(cache)) = (writer.finish(failed), cache) {
+                drop(writer);
+                let _ = cache.store(&key, &data).await;
+            }
+        });
+
+        stream.downloading(task.abort_handle());
+        Ok(Data::Streaming(stream))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{borrow::Cow, collections::HashMap};
+
+    use rand::{rngs::StdRng, SeedableRng};
+    use reqwest::Url;
+
+    use super::{List, Source, BASE_URL, UNTITLED};
+
+    /// Creates a local list from `text`, just like it would be read from a file.
+    fn list(text: &'static str) -> List {
+        let (lines, weights) = List::parse(Cow::Borrowed(text));
+        List {
+            weights,
+            ..List::new(Source::Local, lines)
+        }
+    }
+
+    #[test]
+    fn start_positions_begin_at_one() {
+        let list = list("/music/rain.mp3\n/music/snow.mp3\n");
+        assert_eq!(list.find("1"), Some(0));
+        assert_eq!(list.find("2"), Some(1));
+        assert_eq!(list.find("0"), None);
+        assert_eq!(list.find("3"), None);
+        assert_eq!(list.find("SNOW"), Some(1));
+    }
+
+    #[test]
+    fn blank_names_are_untitled() {
+        let remote = List::new(
+            Source::Remote(Url::parse(BASE_URL).unwrap()),
+            List::parse(Cow::Borrowed("")).0,
+        );
+        assert_eq!(remote.name("2023/04/123.mp3"), UNTITLED);
+        assert_eq!(remote.name("2023/04/01 Snow.mp3"), "Snow");
+
+        let local = list("");
+        assert_eq!(local.name("/music/ _ .mp3"), UNTITLED);
+        assert_eq!(local.name("/music/snow day.mp3"), "snow day");
+
+        let raw = List {
+            raw: true,
+            ..list("")
+        };
+        assert_eq!(raw.name("music/"), UNTITLED);
+
+        let titled = List {
+            titles: HashMap::from([(String::from("a.mp3"), String::from(" \t "))]),
+            ..list("")
+        };
+        assert_eq!(titled.name("a.mp3"), UNTITLED);
+    }
+
+    #[test]
+    fn windows_line_endings_and_a_bom_are_ignored() {
+        let text = "\u{feff}first.mp3\r\nsecond.mp3\t2\r\n# comment\r\n\r\n  third.mp3  \r\n";
+        let (lines, weights) = List::parse(Cow::Borrowed(text));
+
+        let lines: Vec<&str> = lines.iter().collect();
+        assert_eq!(lines, ["first.mp3", "second.mp3", "third.mp3"]);
+        assert_eq!(weights.len(), 1);
+        assert_eq!(weights.get("second.mp3"), Some(&2));
+    }
+
+    #[test]
+    fn weights_are_followed() {
+        let list = list("heavy.mp3\t3\nusual.mp3\nnever.mp3\t0\n");
+        let mut rng = StdRng::seed_from_u64(76);
+
+        let mut picked = [0; 3];
+        for _ in 0..4000 {
+            picked[list.shuffled(&mut rng)] += 1;
+        }
+
+        assert_eq!(picked[2], 0, "{picked:?}");
+        assert!((2850..3150).contains(&picked[0]), "{picked:?}");
+        assert!((850..1150).contains(&picked[1]), "{picked:?}");
+    }
+
+    #[test]
+    fn every_weight_being_zero_is_uniform() {
+        let list = list("a.mp3\t0\nb.mp3\t0\n");
+        let mut rng = StdRng::seed_from_u64(76);
+
+        let mut picked = [0; 2];
+        for _ in 0..2000 {
+            picked[list.shuffled(&mut rng)] += 1;
+        }
+
+        assert!((900..1100).contains(&picked[0]), "{picked:?}");
+    }
+
+    #[test]
+    fn blocking_a_track_after_picking_is_followed() {
+        let list = list("/music/heavy.mp3\t3\n/music/usual.mp3\n");
+        let mut rng = StdRng::seed_from_u64(98);
+        list.shuffled(&mut rng);
+
+        assert_eq!(list.index("/music/heavy.mp3"), Some(0));
+        assert!(list.block("/music/heavy.mp3"));
+        assert!((0..100).all(|_| list.shuffled(&mut rng) == 1));
+    }
+}
@@ -0,0 +1,218 @@
+//! Parses `.m3u`, `.m3u8` & `.pls` playlists, which is what `--playlist` plays from.
+
+use std::{
+    collections::BTreeMap,
+    path::{Path, PathBuf},
+};
+
+use reqwest::Url;
+
+/// A single track in a playlist, which is either a full path or an `http(s)` URL.
+pub struct Entry {
+    pub track: String,
+
+    /// The title from `#EXTINF` or `TitleN`, which is shown instead of the file name.
+    pub title: Option<String>,
+}
+
+/// Everything that could be read from a playlist.
+pub struct Playlist {
+    pub entries: Vec<Entry>,
+
+    /// How many entries were skipped, since they were malformed or the file wasn't there.
+    pub skipped: usize,
+}
+
+impl Playlist {
+    /// Parses the contents of the playlist at `path`, whose format is decided by its extension.
+    ///
+    /// Relative paths in the playlist are relative to the directory that the playlist is in.
+    pub fn parse(path: &Path, text: &str) -> Self {
+        let dir = path.parent().unwrap_or(Path::new(""));
+        let text = text.trim_start_matches('\u{feff}');
+
+        let pls = path
+            .extension()
+            .is_some_and(|x| x.eq_ignore_ascii_case("pls"))
+            || text.trim_start().to_lowercase().starts_with("[playlist]");
+
+        let (items, mut skipped) = if pls {
+            Self::pls(text)
+        } else {
+            Self::m3u(text)
+        };
+
+        let mut entries = Vec::with_capacity(items.len());
+        for (track, title) in items {
+            match Self::resolve(dir, &track) {
+                Some(track) => entries.push(Entry { track, title }),
+                None => skipped += 1,
+            }
+        }
+
+        Self { entries, skipped }
+    }
+
+    /// Reads an M3U playlist, where every line which isn't a comment is a track,
+    /// and `#EXTINF:<duration>,<title>` gives the title of the track after it.
+    fn m3u(text: &str) -> (Vec<(String, Option<String>)>, usize) {
+        let mut items = Vec::new();
+        let mut title = None;
+
+        for line in text.lines().map(str::trim).filter(|x| !x.is_empty()) {
+            if let Some(info) = line.strip_prefix("#EXTINF:") {
+                title = info
+                    .split_once(',')
+                    .map(|(_, x)| x.trim().to_owned())
+                    .filter(|x| !x.is_empty());
+            } else if !line.starts_with('#') {
+                items.push((line.to_owned(), title.take()));
+            }
+        }
+
+        (items, 0)
+    }
+
+    /// Reads a PLS playlist, which is like an INI file with `FileN` & `TitleN` keys.
+    fn pls(text: &str) -> (Vec<(String, Option<String>)>, usize) {
+        let mut items: BTreeMap<u32, (Option<String>, Option<String>)> = BTreeMap::new();
+        let mut skipped = 0;
+
+        for line in text.lines().map(str::trim) {
+            if line.is_empty() || line.starts_with(['[', ';', '#']) {
+                continue;
+            }
+
+            let Some((key, value)) = line.split_once('=') else {
+                skipped += 1;
+                continue;
+            };
+
+            let key = key.trim().to_lowercase();
+            let value = value.trim().to_owned();
+            let (field, number) = if let Some(number) = key.strip_prefix("file") {
+                (0, number)
+            } else if let Some(number) = key.strip_prefix("title") {
+                (1, number)
+            } else {
+                // This is stuff like `NumberOfEntries` & `Version`, which don't matter.
+                continue;
+            };
+
+            let Ok(number) = number.parse() else {
+                skipped += 1;
+                continue;
+            };
+
+            let item = items.entry(number).or_default();
+            match field {
+                0 => item.0 = Some(value),
+                _ => item.1 = Some(value).filter(|x| !x.is_empty()),
+            }
+        }
+
+        let mut result = Vec::with_capacity(items.len());
+        for (file, title) in items.into_values() {
+            match file {
+                Some(file) => result.push((file, title)),
+                None => skipped += 1,
+            }
+        }
+
+        (result, skipped)
+    }
+
+    /// Turns a track from a playlist into either a URL or a full path, relative to `dir`.
+    ///
+    /// This is [None] for URLs which lowfi can't play, or paths that don't exist.
+    fn resolve(dir: &Path, track: &str) -> Option<String> {
+        let path = match Url::parse(track) {
+            Ok(url) if matches!(url.scheme(), "http" | "https") => return Some(url.into()),
+            Ok(url) if url.scheme() == "file" => url.to_file_path().ok()?,
+
+            // Windows paths like `C:\Music` look like URLs with a single letter scheme.
+            Ok(url) if url.scheme().len() > 1 => return None,
+            _ => PathBuf::from(track),
+        };
+
+        let path = if path.is_relative() {
+            dir.join(path)
+        } else {
+            path
+        };
+
+        path.exists().then(|| path.to_string_lossy().into_owned())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use super::Playlist;
+
+    #[test]
+    fn m3u_titles_come_from_extinf() {
+        let text = "#EXTM3U\n#EXTINF:123,Snow Day\nhttps://example.com/a.mp3\n\n# comment\nhttps://example.com/b.mp3\n#EXTINF:-1,\nhttps://example.com/c.mp3\n";
+        let (items, skipped) = Playlist::m3u(text);
+
+        assert_eq!(skipped, 0);
+        assert_eq!(
+            items,
+            [
+                (
+                    String::from("https://example.com/a.mp3"),
+                    Some(String::from("Snow Day"))
+                ),
+                (String::from("https://example.com/b.mp3"), None),
+                (String::from("https://example.com/c.mp3"), None),
+            ]
+        );
+    }
+
+    #[test]
+    fn pls_entries_are_numbered() {
+        let text = "[playlist]\nFile3=c.mp3\nTitle3=Third\nFile1=a.mp3\nbroken line\nTitle2=Missing\nFileX=x.mp3\nFile10=j.mp3\nNumberOfEntries=4\nVersion=2\n";
+        let (items, skipped) = Playlist::pls(text);
+
+        // The broken line, `FileX`, and `Title2` without a `File2` are all skipped.
+        assert_eq!(skipped, 3);
+        assert_eq!(
+            items,
+            [
+                (String::from("a.mp3"), None),
+                (String::from("c.mp3"), Some(String::from("Third"))),
+                (String::from("j.mp3"), None),
+            ]
+        );
+    }
+
+    #[test]
+    fn tracks_are_resolved_relative_to_the_playlist() {
+        let dir = std::env::temp_dir().join(format!("lowfi-playlist-{}", std::process::id()));
+        std::fs::create_dir_all(dir.join("music")).unwrap();
+        std::fs::write(dir.join("music/rain.mp3"), "").unwrap();
+
+        let text = "music/rain.mp3\nmusic/missing.mp3\nhttps://example.com/a.mp3\nftp://example.com/b.mp3\nspotify:track:123\n";
+        let playlist = Playlist::parse(&dir.join("list.m3u"), text);
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        let tracks: Vec<&str> = playlist.entries.iter().map(|x| x.track.as_str()).collect();
+        let rain = dir.join("music/rain.mp3");
+        assert_eq!(
+            tracks,
+            [rain.to_str().unwrap(), "https://example.com/a.mp3"]
+        );
+        assert_eq!(playlist.skipped, 3);
+    }
+
+    #[test]
+    fn pls_is_detected_from_the_header() {
+        let playlist = Playlist::parse(
+            Path::new("list.txt"),
+            "[playlist]\nFile1=https://example.com/a.mp3\n",
+        );
+        assert_eq!(playlist.entries.len(), 1);
+        assert_eq!(playlist.entries[0].track, "https://example.com/a.mp3");
+    }
+}
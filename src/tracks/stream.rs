@@ -0,0 +1,491 @@
+//! Lets a track start playing before it's been completely downloaded.
+//!
+//! The response is written into a [Stream] in the background, and a [Reader]
+//! reads from it, waiting whenever it gets ahead of the download. That waiting
+//! happens on a thread of its own, see [Decoded], so the audio never blocks.
+//! Once every [Reader] and the [Stream] itself are gone, the download is stopped.
+
+use std::{
+    collections::VecDeque,
+    io::{self, Read, Seek, SeekFrom},
+    sync::{Arc, Condvar, Mutex},
+    time::Duration,
+};
+
+use bytes::Bytes;
+use rodio::{source::SeekError, Source};
+use tokio::{sync::Notify, task::AbortHandle};
+
+/// How much of a streamed track has to arrive before it starts playing, in bytes.
+pub const PREBUFFER: usize = 128 * 1024;
+
+/// How many samples [Decoded] stays ahead of what's playing, which is about a second of stereo audio.
+const AHEAD: usize = 96 * 1024;
+
+/// How many samples [Decoded] decodes at once, in between checking for seeks.
+const CHUNK: usize = 4096;
+
+/// Everything that's been downloaded so far, alongside whether the download has finished.
+#[derive(Default)]
+struct State {
+    data: Vec<u8>,
+
+    /// The size of the whole track, which is [None] if the server didn't say.
+    length: Option<u64>,
+
+    /// This is [None] while still downloading, and then whether it succeeded.
+    finished: Option<bool>,
+}
+
+/// A track which is still being downloaded, and can be read by any amount of [Reader]s.
+pub struct Stream {
+    state: Mutex<State>,
+
+    /// Wakes up readers which are waiting for more data.
+    arrived: Condvar,
+
+    /// The same as `arrived`, but for [Stream::buffered].
+    notify: Notify,
+
+    /// The task which is doing the download, see [Stream::downloading].
+    task: Mutex<Option<AbortHandle>>,
+}
+
+impl Stream {
+    /// Creates an empty stream, where `length` is the size of the track if it's known.
+    pub fn new(length: Option<u64>) -> Arc<Self> {
+        Arc::new(Self {
+            state: Mutex::new(State {
+                length,
+                ..State::default()
+            }),
+            arrived: Condvar::new(),
+            notify: Notify::new(),
+            task: Mutex::new(None),
+        })
+    }
+
+    /// Adds a chunk that just arrived to the end of the stream.
+    pub fn push(&self, chunk: &[u8]) {
+        self.state.lock().unwrap().data.extend_from_slice(chunk);
+        self.wake();
+    }
+
+    /// Marks the download as done, which also ends it early if it `failed`.
+    ///
+    /// The whole track is returned if it succeeded, so that it can be cached.
+    pub fn finish(&self, failed: bool) -> Option<Bytes> {
+        let mut state = self.state.lock().unwrap();
+        state.finished = Some(!failed);
+        let data = (!failed).then(|| Bytes::copy_from_slice(&state.data));
+        drop(state);
+
+        self.wake();
+        data
+    }
+
+    /// Whether the download is still going.
+    pub fn is_streaming(&self) -> bool {
+        self.state.lock().unwrap().finished.is_none()
+    }
+
+    /// How many bytes have been pushed so far, which is where a resumed download picks up from.
+    pub fn downloaded(&self) -> usize {
+        self.state.lock().unwrap().data.len()
+    }
+
+    /// Waits until `amount` bytes have arrived, or the download is over.
+    pub async fn buffered(&self, amount: usize) {
+        loop {
+            // This has to be created before checking, otherwise a wakeup could be missed.
+            let notified = self.notify.notified();
+
+            {
+                let state = self.state.lock().unwrap();
+                if state.data.len() >= amount || state.finished.is_some() {
+                    return;
+                }
+            }
+
+            notified.await;
+        }
+    }
+
+    /// Creates a new [Reader], which starts at the beginning of the track.
+    pub fn reader(self: &Arc<Self>) -> Reader {
+        Reader {
+            stream: Arc::clone(self),
+            position: 0,
+        }
+    }
+
+    /// Sets the `task` that's downloading into this stream, which is aborted once the stream is dropped.
+    ///
+    /// The task should only hold onto a [std::sync::Weak] reference, otherwise it'd never be dropped.
+    pub fn downloading(&self, task: AbortHandle) {
+        *self.task.lock().unwrap() = Some(task);
+    }
+
+    fn wake(&self) {
+        self.arrived.notify_all();
+        self.notify.notify_waiters();
+    }
+}
+
+impl Drop for Stream {
+    fn drop(&mut self) {
+        if let Some(task) = self.task.get_mut().unwrap().take() {
+            task.abort();
+        }
+    }
+}
+
+/// Reads from a [Stream], which blocks while waiting for the download to catch up.
+///
+/// This should only ever be read from a thread that's allowed to block, like the one from [Decoded].
+/// If the download fails partway through, then this errors once it reaches that point.
+pub struct Reader {
+    stream: Arc<Stream>,
+    position: u64,
+}
+
+impl Read for Reader {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let mut state = self.stream.state.lock().unwrap();
+
+        loop {
+            let available = state.data.len() as u64;
+            if self.position < available {
+                let start = self.position as usize;
+                let amount = buf.len().min(state.data.len() - start);
+                buf[..amount].copy_from_slice(&state.data[start..start + amount]);
+                self.position += amount as u64;
+
+                return Ok(amount);
+            }
+
+            match state.finished {
+                Some(true) => return Ok(0),
+                Some(false) => return Err(io::Error::other("the download was interrupted")),
+                None => state = self.stream.arrived.wait(state).unwrap(),
+            }
+        }
+    }
+}
+
+impl Seek for Reader {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        let target = match pos {
+            SeekFrom::Start(x) => Some(x),
+            SeekFrom::Current(x) => self.position.checked_add_signed(x),
+            SeekFrom::End(x) => {
+                let mut state = self.stream.state.lock().unwrap();
+
+                // Without a length, the end isn't known until everything has arrived.
+                while state.length.is_none() && state.finished.is_none() {
+                    state = self.stream.arrived.wait(state).unwrap();
+                }
+
+                let length = match state.finished {
+                    Some(_) => state.data.len() as u64,
+                    None => state.length.unwrap_or_default(),
+                };
+
+                length.checked_add_signed(x)
+            }
+        };
+
+        self.position = target.ok_or_else(|| {
+            io::Error::new(io::ErrorKind::InvalidInput, "can't seek before the start")
+        })?;
+
+        Ok(self.position)
+    }
+}
+
+/// The samples which [Decoded] has decoded ahead of time, shared with its decoding thread.
+#[derive(Default)]
+struct Decoding {
+    samples: VecDeque<i16>,
+
+    /// Whether the decoder has run out, which is either the end of the track or a failed download.
+    done: bool,
+
+    /// Where to seek to, which the decoding thread does before decoding anything else.
+    seek: Option<Duration>,
+
+    /// Goes up with every seek, so that samples decoded from before one are thrown away.
+    generation: u64,
+
+    /// Whether the [Decoded] is gone, in which case the decoding thread stops.
+    dropped: bool,
+
+    /// How many samples of silence have been played during underruns since the last seek.
+    silence: u64,
+}
+
+#[derive(Default)]
+struct Shared {
+    decoding: Mutex<Decoding>,
+
+    /// Wakes up the decoding thread once there's room for more samples, or something changed.
+    changed: Condvar,
+}
+
+/// Decodes a streamed track on its own thread, so that waiting for the download never blocks the audio.
+///
+/// Whenever it runs out of samples before the track is over, this just plays silence
+/// until the download catches up, which is what handles underruns. That silence
+/// is counted by [Underruns], so that it can be left out of the track's position.
+pub struct Decoded {
+    shared: Arc<Shared>,
+    channels: u16,
+    sample_rate: u32,
+    duration: Option<Duration>,
+
+    /// How far into the current frame the next sample is, counting silence too.
+    offset: u16,
+
+    /// How far into its frame the next decoded sample is, which only counts decoded samples.
+    /// Those are only played once `offset` matches, so that silence never swaps the channels.
+    phase: u16,
+}
+
+/// How much silence a [Decoded] has played while waiting for the download.
+///
+/// The sink counts that silence as part of the track, so this is what's
+/// needed to work out where the track has really gotten to.
+#[derive(Clone)]
+pub struct Underruns {
+    shared: Arc<Shared>,
+
+    /// How many samples make up a second, across every channel.
+    rate: u32,
+}
+
+impl Underruns {
+    /// The total length of the silence since the last seek.
+    pub fn duration(&self) -> Duration {
+        let silence = self.shared.decoding.lock().unwrap().silence;
+        Duration::from_secs_f64(silence as f64 / f64::from(self.rate.max(1)))
+    }
+}
+
+impl Decoded {
+    /// Starts decoding `decoder` in the background, which is usually a [rodio::Decoder] reading from a [Reader].
+    pub fn new<S>(decoder: S) -> Self
+    where
+        S: Source<Item = i16> + Send + 'static,
+    {
+        let shared = Arc::new(Shared::default());
+        let decoded = Self {
+            shared: Arc::clone(&shared),
+            channels: decoder.channels(),
+            sample_rate: decoder.sample_rate(),
+            duration: decoder.total_duration(),
+            offset: 0,
+            phase: 0,
+        };
+
+        std::thread::spawn(move || Self::decode(decoder, &shared));
+        decoded
+    }
+
+    /// Gets a handle to the silence that's been played during underruns.
+    pub fn underruns(&self) -> Underruns {
+        Underruns {
+            shared: Arc::clone(&self.shared),
+            rate: self.sample_rate * u32::from(self.channels),
+        }
+    }
+
+    /// Keeps [AHEAD] samples decoded until the [Decoded] is dropped, which runs on its own thread.
+    ///
+    /// This keeps going after the end of the track, since it could still be seeked back into.
+    fn decode(mut decoder: impl Source<Item = i16>, shared: &Shared) {
+        let mut chunk = Vec::with_capacity(CHUNK);
+
+        loop {
+            let generation = {
+                let mut decoding = shared.decoding.lock().unwrap();
+                while (decoding.done || decoding.samples.len() >= AHEAD)
+                    && decoding.seek.is_none()
+                    && !decoding.dropped
+                {
+                    decoding = shared.changed.wait(decoding).unwrap();
+                }
+
+                if decoding.dropped {
+                    return;
+                }
+
+                if let Some(position) = decoding.seek.take() {
+                    drop(decoding);
+
+                    // A seek which fails just carries on from wherever the decoder was.
+                    let _ = decoder.try_seek(position);
+                    continue;
+                }
+
+                decoding.generation
+            };
+
+            // The lock isn't held while decoding, since this is where the download is waited on.
+            chunk.extend(decoder.by_ref().take(CHUNK));
+            let over = chunk.len() < CHUNK;
+
+            let mut decoding = shared.decoding.lock().unwrap();
+            if decoding.generation == generation {
+                decoding.samples.extend(chunk.drain(..));
+                decoding.done = over;
+            } else {
+                chunk.clear();
+            }
+        }
+    }
+}
+
+impl Drop for Decoded {
+    fn drop(&mut self) {
+        self.shared.decoding.lock().unwrap().dropped = true;
+        self.shared.changed.notify_one();
+    }
+}
+
+impl Iterator for Decoded {
+    type Item = i16;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let mut decoding = self.shared.decoding.lock().unwrap();
+
+        let sample = if self.offset == self.phase {
+            decoding.samples.pop_front()
+        } else {
+            None
+        };
+
+        let sample = match sample {
+            Some(sample) => {
+                self.phase = (self.phase + 1) % self.channels;
+                if decoding.samples.len() == AHEAD / 2 {
+                    self.shared.changed.notify_one();
+                }
+
+                sample
+            }
+            None if decoding.done && decoding.samples.is_empty() => return None,
+            None => {
+                decoding.silence += 1;
+                0
+            }
+        };
+
+        self.offset = (self.offset + 1) % self.channels;
+        Some(sample)
+    }
+}
+
+impl Source for Decoded {
+    fn current_frame_len(&self) -> Option<usize> {
+        None
+    }
+
+    fn channels(&self) -> u16 {
+        self.channels
+    }
+
+    fn sample_rate(&self) -> u32 {
+        self.sample_rate
+    }
+
+    fn total_duration(&self) -> Option<Duration> {
+        self.duration
+    }
+
+    /// This only asks for the seek, which the decoding thread does as soon as it can,
+    /// so it never actually fails, and there's just silence until the decoder gets there.
+    fn try_seek(&mut self, position: Duration) -> Result<(), SeekError> {
+        let mut decoding = self.shared.decoding.lock().unwrap();
+        decoding.seek = Some(position);
+        decoding.generation += 1;
+        decoding.samples.clear();
+        decoding.done = false;
+        decoding.silence = 0;
+        drop(decoding);
+
+        self.phase = 0;
+        self.shared.changed.notify_one();
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        sync::mpsc::{self, Receiver},
+        thread,
+        time::Duration,
+    };
+
+    use rodio::Source;
+
+    use super::Decoded;
+
+    /// A mono source which blocks until each sample is sent, just like a download that's behind.
+    struct Behind(Receiver<i16>);
+
+    impl Iterator for Behind {
+        type Item = i16;
+
+        fn next(&mut self) -> Option<Self::Item> {
+            self.0.recv().ok()
+        }
+    }
+
+    impl Source for Behind {
+        fn current_frame_len(&self) -> Option<usize> {
+            None
+        }
+
+        fn channels(&self) -> u16 {
+            1
+        }
+
+        fn sample_rate(&self) -> u32 {
+            1
+        }
+
+        fn total_duration(&self) -> Option<Duration> {
+            None
+        }
+    }
+
+    #[test]
+    fn underruns_are_silent() {
+        let (sender, receiver) = mpsc::channel();
+        let mut decoded = Decoded::new(Behind(receiver));
+
+        // Nothing has arrived, so this has to be silence rather than waiting.
+        assert_eq!(decoded.next(), Some(0));
+        assert_eq!(decoded.underruns().duration(), Duration::from_secs(1));
+
+        sender.send(5).unwrap();
+        sender.send(6).unwrap();
+        drop(sender);
+
+        let mut samples = Vec::new();
+        for _ in 0..1000 {
+            match decoded.next() {
+                Some(0) => thread::sleep(Duration::from_millis(1)),
+                Some(sample) => samples.push(sample),
+                None => break,
+            }
+        }
+
+        assert_eq!(samples, [5, 6]);
+        assert_eq!(decoded.next(), None);
+
+        decoded.try_seek(Duration::ZERO).unwrap();
+        assert_eq!(decoded.underruns().duration(), Duration::ZERO);
+    }
+}